@@ -53,11 +53,13 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     let single_json_build_config = BuildConfig {
         delimiter: None,
         ignore_outer_array: false,
+        ..Default::default()
     };
 
     let multi_json_build_config = BuildConfig {
         delimiter: Some("\n".as_bytes()[0]),
         ignore_outer_array: true,
+        ..Default::default()
     };
 
     let test_json_tiny = create_test_json_str(1, true);
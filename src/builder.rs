@@ -1,7 +1,16 @@
+use std::sync::Arc;
+
 use serde_json::{Value, json};
 use simd_json;
 
-use crate::node::{DataType, SchemaNode};
+use crate::config::{NullableStyle, SchemaConfig};
+use crate::lexeme::Lexeme;
+use crate::node::{DataType, NodeContext, SchemaNode};
+use crate::strategy::discriminator::DISCRIMINATOR_VALUE_KEYWORD;
+use crate::strategy::encoded::NATIVE_VALUES_KEYWORD;
+use crate::strategy::format::FLOAT_FORMAT_KEYWORD;
+use crate::strategy::object::{OBJECT_COUNT_KEYWORD, PROPERTY_COUNTS_KEYWORD};
+use crate::schema::{extract_definitions, extract_discriminator_variants, fold_recursive_structures, inline_local_references, is_draft_2019_09_or_later, visit_schemas_mut};
 
 const DEFAULT_SCHEMA_URI: &str = "http://json-schema.org/schema#";
 const NULL_SCHEMA_URI: &str = "NULL";
@@ -10,6 +19,8 @@ const NULL_SCHEMA_URI: &str = "NULL";
 pub struct SchemaBuilder {
     schema_uri: Option<String>,
     root_node: SchemaNode,
+    config: Arc<SchemaConfig>,
+    object_count: usize,
 }
 
 impl SchemaBuilder {
@@ -18,28 +29,52 @@ impl SchemaBuilder {
     /// schema URI was detected, a default URI of "http://json-schema.org/schema#" will be used. 
     /// A value of None will leave out the "$schema" keyword in the output schema.
     pub fn new(schema_uri: Option<&str>) -> Self {
+        SchemaBuilder::with_config(schema_uri, SchemaConfig::default())
+    }
+
+    /// Create a new SchemaBuilder object that infers the schema according to the given
+    /// configuration. See `SchemaBuilder::new` for the meaning of the schema_uri parameter.
+    pub fn with_config(schema_uri: Option<&str>, config: SchemaConfig) -> Self {
+        SchemaBuilder::with_shared_config(schema_uri, Arc::new(config))
+    }
+
+    pub(crate) fn with_shared_config(schema_uri: Option<&str>, config: Arc<SchemaConfig>) -> Self {
         // TODO: the functionality to allow non-default node class with extended 
         //  strategies is not supported yet
-        let root_node: SchemaNode = SchemaNode::new();
+        let root_node: SchemaNode = SchemaNode::new(NodeContext::root(config.clone()));
 
         if let Some(uri) = schema_uri {
             if uri == "AUTO" {
-                SchemaBuilder { schema_uri: None, root_node }
+                SchemaBuilder { schema_uri: None, root_node, config, object_count: 0 }
             } else {
-                SchemaBuilder { schema_uri: Some(uri.to_string()), root_node }
+                SchemaBuilder { schema_uri: Some(uri.to_string()), root_node, config, object_count: 0 }
             }
         } else {
-            SchemaBuilder { schema_uri: Some(NULL_SCHEMA_URI.to_string()), root_node }
+            SchemaBuilder { schema_uri: Some(NULL_SCHEMA_URI.to_string()), root_node, config, object_count: 0 }
         }
     }
 
+    /// Get the configuration the schema is inferred with
+    pub fn config(&self) -> &SchemaConfig {
+        &self.config
+    }
+
+    /// Get a reference counted handle to the configuration, used to create other builders
+    /// (e.g. one per parallel chunk) that share the same configuration
+    pub(crate) fn shared_config(&self) -> Arc<SchemaConfig> {
+        self.config.clone()
+    }
+
     /// Merge in raw JSON schema object
     pub fn add_schema(&mut self, mut schema: Value) {
         if let Value::Object(ref mut schema_obj) = schema {
-            if schema_obj.contains_key("$schema") && self.schema_uri.is_none() {
-                self.schema_uri = Some(schema_obj["$schema"].as_str().unwrap().to_string());
-                schema_obj.remove("$schema");
+            // the "$schema" keyword only belongs at the root, the first URI seen is kept
+            if let Some(schema_uri) = schema_obj.remove("$schema") {
+                if self.schema_uri.is_none() {
+                    self.schema_uri = Some(schema_uri.as_str().unwrap().to_string());
+                }
             }
+            inline_local_references(&mut schema);
             self.root_node.add_schema(DataType::Schema(&schema));
         } else {
             panic!("Invalid schema type - must be a valid JSON object")
@@ -51,13 +86,87 @@ impl SchemaBuilder {
         self.root_node.add_schema(DataType::SchemaNode(&node));
     }
 
+    /// Merge in the schema of another builder, along with the count of objects it was built from
+    pub fn add_builder(&mut self, other: &SchemaBuilder) {
+        // the post-processing only runs once, when the final schema is exported
+        let mut schema = other.to_raw_schema();
+        if other.schema_uri.is_none() {
+            // the other builder hasn't detected a schema URI, so it emits the default one
+            schema.as_object_mut().unwrap().remove("$schema");
+        }
+        self.add_schema(schema);
+        self.object_count += other.object_count;
+    }
+
     /// Modify the schema to accomodate the input object
     pub fn add_object(&mut self, object: &simd_json::BorrowedValue) {
         self.root_node.add_object(DataType::Object(object));
+        self.object_count += 1;
+    }
+
+    /// Get the number of objects the schema was built from, including the objects of the
+    /// builders merged in with `add_builder`
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    /// Get the number of changes to the structure of the schema so far, i.e. new types,
+    /// properties or array items, properties that are no longer required and integers that
+    /// became numbers. The count doesn't change when an object fits the schema as it is.
+    pub fn structure_changes(&self) -> usize {
+        self.root_node.structure_changes()
+    }
+
+    /// Add the text of the object that was last added, to track what can only be told from
+    /// the text of its numbers
+    pub(crate) fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        self.root_node.add_lexeme(lexeme);
     }
 
     /// Export the currently constructed schema as a JSON object
     pub fn to_schema(&self) -> Value {
+        let mut base_schema = self.to_raw_schema();
+        let uses_2019_09_keywords = base_schema["$schema"].as_str().is_some_and(is_draft_2019_09_or_later);
+        let definitions_keyword = if uses_2019_09_keywords { "$defs" } else { "definitions" };
+        if self.config.recursive_structures {
+            // the nested structures are merged with the keywords used to merge builders
+            fold_recursive_structures(&mut base_schema, definitions_keyword, &|schema1, schema2| {
+                self.merge_schemas(schema1, schema2)
+            });
+        }
+        // the keywords only used to merge builders are not part of the schema
+        visit_schemas_mut(&mut base_schema, &mut |schema| {
+            if let Value::Object(schema) = schema {
+                schema.remove(OBJECT_COUNT_KEYWORD);
+                schema.remove(PROPERTY_COUNTS_KEYWORD);
+                schema.remove(FLOAT_FORMAT_KEYWORD);
+                schema.remove(DISCRIMINATOR_VALUE_KEYWORD);
+                schema.remove(NATIVE_VALUES_KEYWORD);
+            }
+        });
+        if self.config.nullable_style == NullableStyle::OpenApi {
+            // the discriminator maps the values to the variants by reference
+            extract_discriminator_variants(&mut base_schema, definitions_keyword);
+        }
+        if self.config.strict_objects && uses_2019_09_keywords {
+            // closed objects are expressed with "unevaluatedProperties" from draft 2019-09 onwards
+            visit_schemas_mut(&mut base_schema, &mut |schema| {
+                if schema["additionalProperties"] == false {
+                    let schema = schema.as_object_mut().unwrap();
+                    schema.remove("additionalProperties");
+                    schema.insert("unevaluatedProperties".to_string(), false.into());
+                }
+            });
+        }
+        if let Some(min_size) = self.config.extract_definitions {
+            extract_definitions(&mut base_schema, definitions_keyword, min_size);
+        }
+        return base_schema;
+    }
+
+    /// Export the schema of the nodes as they are, without the post-processing of `to_schema`
+    /// (e.g. recursive structures and definitions), to be merged into another builder
+    fn to_raw_schema(&self) -> Value {
         let mut base_schema = self.get_base_schema();
         
         let base_schema_map = base_schema.as_object_mut().unwrap();
@@ -67,7 +176,15 @@ impl SchemaBuilder {
         for (key, value) in node_schema_map.iter() {
             base_schema_map.insert(key.to_string(), value.clone());
         }
-        return base_schema;
+        base_schema
+    }
+
+    /// Merge two schemas into one, as they would be merged into the root of the schema
+    fn merge_schemas(&self, schema1: &Value, schema2: &Value) -> Value {
+        let mut node = SchemaNode::new(NodeContext::root(self.config.clone()));
+        node.add_schema(DataType::Schema(schema1));
+        node.add_schema(DataType::Schema(schema2));
+        node.to_schema()
     }
 
     fn get_base_schema(&self) -> Value {
@@ -0,0 +1,171 @@
+use std::fmt;
+use std::sync::Arc;
+
+use regex::Regex;
+use serde_json::Value;
+
+/// Configuration for how a schema gets inferred from the input objects. Every option
+/// is opt-in, the default configuration generates the same schema as GenSON does.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaConfig {
+    /// Track the observed string lengths, array lengths and object property counts, and
+    /// emit them as "minLength"/"maxLength", "minItems"/"maxItems" and "minProperties"/"maxProperties"
+    pub size_constraints: bool,
+    /// Generalize the observed strings into a "pattern" made of character classes (digits,
+    /// upper and lower case letters) and literal separators, no pattern is emitted when the
+    /// strings are too diverse to share one
+    pub string_patterns: bool,
+    /// The maximum number of distinct string, number and boolean values to keep as
+    /// "examples" for each field, a value of 0 disables collecting examples
+    pub max_examples: usize,
+    /// Hook to mask sensitive values before they are collected as examples
+    pub example_redactor: Option<ExampleRedactor>,
+    /// Collapse objects whose keys all share a dynamic shape (integers, UUIDs or dates)
+    /// into "additionalProperties" with the merged schema of their values
+    pub map_key_shapes: bool,
+    /// Collapse objects with more distinct keys than this into "additionalProperties"
+    /// with the merged schema of their values
+    pub map_key_threshold: Option<usize>,
+    /// Describe the keys of the collapsed objects with a "propertyNames" pattern
+    pub map_property_names: bool,
+    /// Properties whose names match one of these regexes are inferred as "patternProperties"
+    /// keyed by the regex, instead of as individual "properties"
+    pub pattern_properties: Vec<Regex>,
+    /// Close every inferred object to properties that were not observed, with
+    /// "additionalProperties": false (or "unevaluatedProperties": false for draft 2019-09 and later)
+    pub strict_objects: bool,
+    /// JSON pointers of the objects that stay open when strict objects are enabled, where
+    /// array items are addressed with "*" (e.g. "/payload" or "/items/*/metadata"). The
+    /// segments can be globs, as in the include and exclude paths (e.g. "/**/extra_*").
+    pub open_objects: Vec<String>,
+    /// Infer arrays with items of different types as tuples (an array of "items" schemas, one
+    /// per position), as long as all of them have the same length and the same type at each
+    /// position. Otherwise arrays fall back to being inferred as lists.
+    pub tuple_detection: bool,
+    /// How fields that can be null are represented in the inferred schema
+    pub nullable_style: NullableStyle,
+    /// Emit unions as "oneOf" instead of "anyOf" when their branches are provably mutually
+    /// exclusive, i.e. every branch has a "type" and no type is shared between branches
+    pub one_of: bool,
+    /// Infer one object schema per value of a discriminator property (e.g. the "type" of
+    /// an event), emitted as a "oneOf" of the variants with a "const" discriminator. The
+    /// discriminator is auto-detected among conventional names like "type" and "kind". In the
+    /// OpenAPI nullable style, the variants are tagged with a single "enum" value instead, moved
+    /// to the definitions, and mapped to by the "discriminator" keyword.
+    pub discriminated_unions: bool,
+    /// Name of the discriminator property, which enables discriminated unions without
+    /// auto-detecting the discriminator
+    pub discriminator_property: Option<String>,
+    /// Mark a property as required when it's present in at least this fraction of the objects
+    /// (e.g. 0.999), rather than only when it's present in all of them. The counts this is
+    /// based on are merged between builders with `SchemaBuilder::add_builder`, and are also
+    /// read from the "x-genson-object-count" and "x-genson-property-counts" keywords of the
+    /// schemas added with `SchemaBuilder::add_schema`.
+    pub required_threshold: Option<f64>,
+    /// Annotate every schema with the number of values observed for it ("x-genson-count"),
+    /// the fraction of them that were null ("x-genson-null-ratio") and the number of values
+    /// of each type ("x-genson-type-distribution")
+    pub stats_annotations: bool,
+    /// Infer floats without a fractional part (e.g. 1.0) as integers, which includes the
+    /// integers beyond 64 bits, as they can only be parsed as floats
+    pub integral_floats: bool,
+    /// Emit the smallest OpenAPI "format" that fits all the observed numbers, "int32" or
+    /// "int64" for integers (none if they don't fit 64 bits), "float" or "double" otherwise,
+    /// where "float" is only emitted when every number is exactly representable as one
+    pub number_formats: bool,
+    /// Detect string fields whose values are all the text of integers, numbers or booleans
+    /// (e.g. "42", "3.14" or "true")
+    pub encoded_scalars: EncodedScalars,
+    /// Detect numeric fields whose values are all Unix epoch timestamps between the years 2000
+    /// and 2100, annotated with their unit as "x-genson-timestamp": "epoch-s", "epoch-ms" or "epoch-us"
+    pub epoch_timestamps: bool,
+    /// Only detect epoch timestamps for the fields whose name ends with one of these
+    /// hints (e.g. "_at", "_ts" or "time"), case-insensitively
+    pub timestamp_name_hints: Vec<String>,
+    /// Track the maximum number of digits of numeric fields, before and after the decimal point,
+    /// annotated as "x-genson-decimal": {"precision", "scale"}. This needs the text of the numbers,
+    /// so it's only tracked for the objects built from JSON text, e.g. with `build_json_schema`.
+    pub decimal_precision: bool,
+    /// Hoist the subschemas that occur more than once, and have at least this many keywords
+    /// (counted recursively, including property names), into "$defs" ("definitions" before
+    /// draft 2019-09) named after their property, and replace them with a "$ref"
+    pub extract_definitions: Option<usize>,
+    /// Fold the objects that are nested in an object of the same shape (e.g. the replies of
+    /// a comment, or the reports of an employee) into the schema of the ancestor, which they
+    /// refer to with a recursive "$ref", instead of unrolling every level of nesting. The
+    /// objects have to be nested under the same property as the ancestor, or have the property
+    /// they're nested under themselves, and stay inline unless the folded schema accepts them.
+    pub recursive_structures: bool,
+    /// Only infer the schema down to this many levels of nesting below the root (e.g. 1 to only
+    /// infer the root properties), the values nested deeper are emitted as the placeholder. The
+    /// objects at the maximum depth don't keep track of their properties, the placeholder is
+    /// their "additionalProperties" instead.
+    pub max_depth: Option<usize>,
+    /// The schema emitted for the values nested deeper than the maximum depth, `{}` by default
+    pub max_depth_placeholder: Option<Value>,
+    /// JSON pointers of the only nodes to infer, along with their ancestors and descendants,
+    /// where array items, map values and pattern property values are addressed with "*". The
+    /// segments can be globs (e.g. "internal_*"), "**" matches any number of segments
+    /// (e.g. "/payload/**"), and pointers that start with "!" are excluded instead (e.g. "!/debug").
+    pub include_paths: Vec<String>,
+    /// JSON pointers of the nodes that are left out of the schema along with their descendants,
+    /// in the same format as `include_paths`. Excluded properties are never inferred.
+    pub exclude_paths: Vec<String>,
+}
+
+impl SchemaConfig {
+    /// Check if any nodes can be left out of the schema by their path
+    pub fn has_path_filters(&self) -> bool {
+        !self.include_paths.is_empty() || !self.exclude_paths.is_empty()
+    }
+}
+
+/// NullableStyle is the representation of nullable fields in the inferred schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullableStyle {
+    /// Same as GenSON: "null" is merged into the "type" array of scalars, but is
+    /// an "anyOf" branch next to objects and arrays
+    #[default]
+    Mixed,
+    /// "null" is always added to the "type" of the schema, e.g. {"type": ["object", "null"]},
+    /// unless the field is both an object and an array, where it's added as an "anyOf" branch
+    TypeArray,
+    /// The schema is always wrapped in an "anyOf" with a {"type": "null"} branch
+    AnyOf,
+    /// The schema is marked with "nullable": true, as in OpenAPI 3.0, which has no type arrays,
+    /// so fields of several types are an "anyOf" of nullable single-type branches. Fields that
+    /// were only ever null are {"type": "string", "nullable": true, "enum": [null]}.
+    OpenApi,
+}
+
+/// EncodedScalars is what's done with string fields that encode numbers or booleans
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncodedScalars {
+    /// The fields are inferred as strings, without detecting the encoded type
+    #[default]
+    Ignore,
+    /// The fields are inferred as strings, annotated with the encoded type as "x-genson-encoded-type"
+    Annotate,
+    /// The fields are inferred as the encoded type, and annotated as strings that encode it
+    Coerce,
+}
+
+/// ExampleRedactor wraps a function that is called with the JSON pointer of the field
+/// (where array items are addressed with "*") and every value observed for it. It returns
+/// the value to collect as an example instead, or None to not collect the value at all.
+#[derive(Clone)]
+pub struct ExampleRedactor(pub Arc<RedactorFn>);
+
+type RedactorFn = dyn Fn(&str, Value) -> Option<Value> + Send + Sync;
+
+impl ExampleRedactor {
+    pub fn new(redactor: impl Fn(&str, Value) -> Option<Value> + Send + Sync + 'static) -> Self {
+        ExampleRedactor(Arc::new(redactor))
+    }
+}
+
+impl fmt::Debug for ExampleRedactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExampleRedactor")
+    }
+}
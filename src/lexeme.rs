@@ -0,0 +1,143 @@
+/// Lexeme is the structure of a JSON value as it appears in the input, where numbers keep
+/// the text they are written with (e.g. the trailing zero of "10.50"), which is lost once
+/// they are parsed. Only the parts needed to route numbers to their schema nodes are kept.
+#[derive(Debug, PartialEq)]
+pub enum Lexeme {
+    Number(String),
+    String(String),
+    Array(Vec<Lexeme>),
+    Object(Vec<(String, Lexeme)>),
+    Other,
+}
+
+impl Lexeme {
+    /// Scan the JSON text of a value, any text that isn't valid JSON is scanned as `Other`
+    pub fn scan(json: &[u8]) -> Lexeme {
+        let mut scanner = Scanner { json, pos: 0 };
+        scanner.scan_value().unwrap_or(Lexeme::Other)
+    }
+}
+
+struct Scanner<'a> {
+    json: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn scan_value(&mut self) -> Option<Lexeme> {
+        self.skip_whitespace();
+        match *self.json.get(self.pos)? {
+            b'{' => self.scan_object(),
+            b'[' => self.scan_array(),
+            b'"' => self.scan_string().map(Lexeme::String),
+            b'-' | b'0'..=b'9' => {
+                let start = self.pos;
+                while self.json.get(self.pos).is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                Some(Lexeme::Number(String::from_utf8_lossy(&self.json[start..self.pos]).to_string()))
+            },
+            _ => {
+                // true, false or null
+                while self.json.get(self.pos).is_some_and(|b| b.is_ascii_alphabetic()) {
+                    self.pos += 1;
+                }
+                Some(Lexeme::Other)
+            },
+        }
+    }
+
+    fn scan_object(&mut self) -> Option<Lexeme> {
+        self.pos += 1;
+        let mut entries = vec![];
+        loop {
+            self.skip_whitespace();
+            match *self.json.get(self.pos)? {
+                b'}' => {
+                    self.pos += 1;
+                    return Some(Lexeme::Object(entries));
+                },
+                b',' => self.pos += 1,
+                _ => {
+                    let key = self.scan_string()?;
+                    self.skip_whitespace();
+                    if self.json.get(self.pos) != Some(&b':') {
+                        return None;
+                    }
+                    self.pos += 1;
+                    entries.push((key, self.scan_value()?));
+                },
+            }
+        }
+    }
+
+    fn scan_array(&mut self) -> Option<Lexeme> {
+        self.pos += 1;
+        let mut items = vec![];
+        loop {
+            self.skip_whitespace();
+            match *self.json.get(self.pos)? {
+                b']' => {
+                    self.pos += 1;
+                    return Some(Lexeme::Array(items));
+                },
+                b',' => self.pos += 1,
+                _ => items.push(self.scan_value()?),
+            }
+        }
+    }
+
+    /// Scan a string and return its unescaped content
+    fn scan_string(&mut self) -> Option<String> {
+        if self.json.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        let start = self.pos;
+        self.pos += 1;
+        let mut has_escapes = false;
+        loop {
+            match *self.json.get(self.pos)? {
+                b'"' => break,
+                b'\\' => {
+                    has_escapes = true;
+                    self.pos += 2;
+                },
+                _ => self.pos += 1,
+            }
+        }
+        self.pos += 1;
+        let text = std::str::from_utf8(&self.json[start..self.pos]).ok()?;
+        if has_escapes {
+            serde_json::from_str(text).ok()
+        } else {
+            Some(text[1..text.len() - 1].to_string())
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.json.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_should_keep_number_text() {
+        let lexeme = Lexeme::scan(br#" {"a\"b": [10.50, -1e3, null], "c": "x"} "#);
+        assert_eq!(lexeme, Lexeme::Object(vec![
+            ("a\"b".to_string(), Lexeme::Array(vec![
+                Lexeme::Number("10.50".to_string()), Lexeme::Number("-1e3".to_string()), Lexeme::Other,
+            ])),
+            ("c".to_string(), Lexeme::String("x".to_string())),
+        ]));
+    }
+
+    #[test]
+    fn test_scan_should_give_up_on_invalid_json() {
+        assert_eq!(Lexeme::scan(br#"{"a": [1, 2"#), Lexeme::Other);
+    }
+}
@@ -1,16 +1,29 @@
 mod node;
 mod strategy;
 mod builder;
+mod config;
+mod schema;
+mod lexeme;
+mod path;
+mod selector;
+
+use std::fmt;
 
 use rayon::prelude::*;
 use mimalloc::MiMalloc;
 use serde_json::json;
 
+use lexeme::Lexeme;
+use schema::visit_schemas_mut;
+use selector::resolve_lexeme;
+
 // Setting the global allocator to mimalloc for more efficient memory allocation
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
 pub use builder::SchemaBuilder;
+pub use config::{EncodedScalars, ExampleRedactor, NullableStyle, SchemaConfig};
+pub use selector::Selector;
 
 pub fn get_builder(schema_uri: Option<&str>) -> SchemaBuilder {
     SchemaBuilder::new(schema_uri)
@@ -18,20 +31,182 @@ pub fn get_builder(schema_uri: Option<&str>) -> SchemaBuilder {
 
 type Schema = serde_json::Value;
 
+/// The maximum nesting depth of the arrays and objects in a JSON object, as inferring the schema
+/// of (and even parsing) deeper objects recurses deep enough to overflow the stack of a thread.
+/// Deeper JSON text is rejected with `BuildError::TooDeeplyNested`, while the values and schemas
+/// added to a `SchemaBuilder` directly are only inferred down to this depth.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
+/// Error returned when a JSON schema can't be built from the data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// A JSON object nests arrays and objects deeper than `MAX_NESTING_DEPTH`
+    TooDeeplyNested,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::TooDeeplyNested => {
+                write!(f, "JSON object is nested deeper than the maximum depth of {}", MAX_NESTING_DEPTH)
+            },
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
 /// Configuration for building a JSON schema
+#[derive(Default)]
 pub struct BuildConfig {
     /// The delimiter to split the JSON objects in the data
     pub delimiter: Option<u8>,
     /// If the outermost array should be ignored if the data is a JSON array of JSON objects
     pub ignore_outer_array: bool,
+    /// Selects the records to build the schema from in each JSON document (e.g. ".data.items[]"),
+    /// instead of building it from the documents themselves. A JSON array is a single document
+    /// when a selector is given. The selected records are what gets skipped and sampled, rather
+    /// than the documents, in which case the documents are parsed one at a time.
+    pub selector: Option<Selector>,
+    /// The number of records to skip from the start of the data
+    pub skip: usize,
+    /// The maximum number of records to build the schema from, after skipping and sampling
+    pub max_records: Option<usize>,
+    /// Only build the schema from every Nth record (after skipping)
+    pub sample_every: Option<usize>,
+    /// The fraction of the records to build the schema from, which are picked at random,
+    /// reproducibly for the same `seed`
+    pub sample_rate: Option<f64>,
+    /// The seed of the random sampling
+    pub seed: u64,
+    /// Stop building the schema once this many consecutive records (after sampling) have not
+    /// changed its structure. The records are added in chunks of this size, and ingestion
+    /// stops after the first chunk that didn't change anything.
+    pub stable_records: Option<usize>,
+}
+
+impl BuildConfig {
+    fn is_sampling(&self) -> bool {
+        self.skip > 0 || self.max_records.is_some() || self.sample_every.is_some() || self.sample_rate.is_some()
+    }
+
+    /// Check if the record at the index (counting only the non-empty records) is sampled,
+    /// without taking the maximum number of records into account
+    fn is_sampled(&self, index: usize) -> bool {
+        if index < self.skip {
+            return false;
+        }
+        if self.sample_every.is_some_and(|every| !(index - self.skip).is_multiple_of(every.max(1))) {
+            return false;
+        }
+        self.sample_rate.is_none_or(|rate| random_fraction(self.seed, index as u64) < rate)
+    }
+}
+
+/// Get a pseudo-random fraction in [0, 1) for the index, which only depends on the seed and
+/// the index, so that the same records are sampled no matter how they are processed in parallel
+fn random_fraction(seed: u64, index: u64) -> f64 {
+    // the finalizer of the SplitMix64 generator
+    let mut hash = seed.wrapping_add(index.wrapping_mul(0x9E3779B97F4A7C15));
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D049BB133111EB);
+    hash ^= hash >> 31;
+    // the 53 most significant bits fit in the mantissa of a float
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// The sampling of the records selected from the documents, which are counted across all of them
+struct SelectedRecordSampling<'c> {
+    config: &'c BuildConfig,
+    // the number of records selected so far
+    index: usize,
+    // the number of records sampled so far
+    sampled: usize,
+}
+
+impl<'c> SelectedRecordSampling<'c> {
+    fn new(config: &'c BuildConfig) -> Self {
+        SelectedRecordSampling { config, index: 0, sampled: 0 }
+    }
+
+    /// Check if the maximum number of records has been sampled
+    fn is_done(&self) -> bool {
+        self.config.max_records.is_some_and(|max_records| self.sampled >= max_records)
+    }
+
+    /// Count the next selected record, and check if it's sampled
+    fn sample_next(&mut self) -> bool {
+        let index = self.index;
+        self.index += 1;
+        if self.is_done() || !self.config.is_sampled(index) {
+            return false;
+        }
+        self.sampled += 1;
+        true
+    }
+}
+
+/// Keep only the sampled records, so that the records that are left out are never parsed
+fn sample_records<'a, 'c>(
+    records: impl Iterator<Item = &'a mut [u8]> + 'c,
+    config: &'c BuildConfig,
+) -> impl Iterator<Item = &'a mut [u8]> + 'c {
+    records
+        // the whitespace between the records (e.g. a trailing newline) isn't a record
+        .filter(|record| record.iter().any(|&c| c == b'{' || c == b'['))
+        .enumerate()
+        .filter(|(index, _)| config.is_sampled(*index))
+        .map(|(_, record)| record)
+        .take(config.max_records.unwrap_or(usize::MAX))
 }
 
 /// Parse a single JSON object and add it to the schema builder
 /// * `builder` - the schema builder object
 /// * `object_slice` - the JSON object to parse
+///
+/// Panics if the JSON object is nested deeper than `MAX_NESTING_DEPTH`, see
+/// `try_build_single_json_object_schema` to handle that as an error instead.
 pub fn build_single_json_object_schema(builder: &mut SchemaBuilder, object_slice: &mut [u8]) {
+    try_build_single_json_object_schema(builder, object_slice).unwrap_or_else(|error| panic!("{}", error));
+}
+
+/// Same as `build_single_json_object_schema`, but returns an error instead of panicking
+/// when the JSON object is nested too deeply to be parsed safely
+pub fn try_build_single_json_object_schema(builder: &mut SchemaBuilder, object_slice: &mut [u8]) -> Result<(), BuildError> {
+    build_selected_records_schema(builder, object_slice, None, None)
+}
+
+/// Parse a single JSON document and add the records the selector selects in it to the schema
+/// builder, or the document itself if there is no selector. The selected records are sampled
+/// with `sampling`, unless they are all added.
+fn build_selected_records_schema(
+    builder: &mut SchemaBuilder,
+    object_slice: &mut [u8],
+    selector: Option<&Selector>,
+    mut sampling: Option<&mut SelectedRecordSampling>,
+) -> Result<(), BuildError> {
+    if exceeds_nesting_depth(object_slice, MAX_NESTING_DEPTH) {
+        return Err(BuildError::TooDeeplyNested);
+    }
+    // the text of the numbers has to be scanned before the object is parsed in place
+    let lexeme = builder.config().decimal_precision.then(|| Lexeme::scan(object_slice));
     let object = simd_json::to_borrowed_value(object_slice).unwrap();
-    builder.add_object(&object);
+    let Some(selector) = selector else {
+        builder.add_object(&object);
+        if let Some(lexeme) = lexeme {
+            builder.add_lexeme(&lexeme);
+        }
+        return Ok(());
+    };
+    selector.select(&object).into_iter()
+        .filter(|_| sampling.as_mut().is_none_or(|sampling| sampling.sample_next()))
+        .for_each(|(record, path)| {
+            builder.add_object(record);
+            if let Some(record_lexeme) = lexeme.as_ref().and_then(|lexeme| resolve_lexeme(lexeme, &path)) {
+                builder.add_lexeme(record_lexeme);
+            }
+        });
+    Ok(())
 }
 
 /// Parse a JSON schema from a JSON object or an array of JSON objects and add it to the schema builder.
@@ -40,38 +215,79 @@ pub fn build_single_json_object_schema(builder: &mut SchemaBuilder, object_slice
 /// * `builder` - the schema builder object
 /// * `json_slice` - the JSON object or array of JSON objects to parse
 /// * `config` - the build configuration
+///
+/// Panics if any of the JSON objects is nested deeper than `MAX_NESTING_DEPTH`, see
+/// `try_build_json_schema` to handle that as an error instead.
 pub fn build_json_schema(builder: &mut SchemaBuilder, json_slice: &mut Vec<u8>, config: &BuildConfig) -> Schema {
+    try_build_json_schema(builder, json_slice, config).unwrap_or_else(|error| panic!("{}", error))
+}
+
+/// Same as `build_json_schema`, but returns an error instead of panicking when any of the
+/// JSON objects is nested too deeply to be parsed safely, in which case the builder is left
+/// untouched. The exception is `BuildConfig::stable_records`, where the chunks of records
+/// before the one with the error have already been added to the builder.
+pub fn try_build_json_schema(builder: &mut SchemaBuilder, json_slice: &mut Vec<u8>, config: &BuildConfig) -> Result<Schema, BuildError> {
     let json_slice = trim_to_object(json_slice);
+    if config.selector.is_some() && is_json_object_array(json_slice) {
+        // the whole array is the document the records are selected from
+        build_selected_records_schema(builder, json_slice, config.selector.as_ref(), Some(&mut SelectedRecordSampling::new(config)))?;
+        return Ok(builder.to_schema());
+    }
     if is_json_object_array(json_slice) {
         let array_elements = get_json_array_elements(json_slice);
-        build_multi_json_objects_schema(builder, array_elements, None);
+        build_multi_json_objects_schema(builder, array_elements, None, config)?;
 
         if config.ignore_outer_array {
-            return builder.to_schema();
+            return Ok(builder.to_schema());
         } else {
             let mut inner_schema = builder.to_schema();
-            let schema_uri = inner_schema.as_object_mut().unwrap().remove("$schema");
-            if let Some(schema_uri) = schema_uri {
-                return json!({"$schema": schema_uri, "type": "array", "items": inner_schema});
+            let inner_schema_map = inner_schema.as_object_mut().unwrap();
+            let schema_uri = inner_schema_map.remove("$schema");
+            // the inner schema is no longer the root that recursive references refer to
+            visit_schemas_mut(&mut inner_schema, &mut |schema| {
+                if schema["$ref"] == "#" {
+                    schema["$ref"] = json!("#/items");
+                }
+            });
+            let inner_schema_map = inner_schema.as_object_mut().unwrap();
+            // the definitions are referred to from the root, so they stay at the root
+            let definitions: Vec<(&str, Schema)> = ["$defs", "definitions"].into_iter()
+                .filter_map(|keyword| inner_schema_map.remove(keyword).map(|definitions| (keyword, definitions)))
+                .collect();
+            let mut schema = if let Some(schema_uri) = schema_uri {
+                json!({"$schema": schema_uri, "type": "array", "items": inner_schema})
             } else {
-                return json!({"type": "array", "items": inner_schema});
-            }
+                json!({"type": "array", "items": inner_schema})
+            };
+            definitions.into_iter().for_each(|(keyword, definitions)| schema[keyword] = definitions);
+            return Ok(schema);
         }
     } else {
-        build_multi_json_objects_schema(builder, json_slice, config.delimiter);
-        return builder.to_schema();
+        build_multi_json_objects_schema(builder, json_slice, config.delimiter, config)?;
+        return Ok(builder.to_schema());
     }
 }
 
-/// Build a JSON schema from multiple JSON objects
+/// Build a JSON schema from multiple JSON objects, the records are sampled while the data is split
+/// into records, before they are parsed
 fn build_multi_json_objects_schema(
     builder: &mut SchemaBuilder,
     json_slice: &mut [u8], 
     delimiter: Option<u8>,
-) {
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    let selector = config.selector.as_ref();
     if let Some(delimiter) = delimiter {
+        if let (Some(selector), true) = (selector, config.is_sampling()) {
+            return add_sampled_selected_records(json_slice.split_mut(|byte| *byte == delimiter), builder, selector, config);
+        }
+        if config.is_sampling() || config.stable_records.is_some() {
+            // the records have to be counted in order to be sampled, or to stop in order
+            let object_slices = sample_records(json_slice.split_mut(|byte| *byte == delimiter), config);
+            return add_schema_from_records(object_slices, builder, selector, config.stable_records);
+        }
         let object_iter = json_slice.par_split_mut(|byte| *byte == delimiter);
-        add_schema_from_object_par_iter(object_iter, builder);
+        add_schema_from_object_par_iter(object_iter, builder, selector)
     } else {
         let mut structure_count = 0;
         let object_separator = move |byte: &u8| -> bool{
@@ -88,32 +304,105 @@ fn build_multi_json_objects_schema(
         };
         // an vector of pointers to each of the individual JSON object bytes in the data
         let mut object_slices: Vec<&mut [u8]> = vec![];
-        json_slice
-            .split_inclusive_mut(object_separator)
-            .for_each(|slice| object_slices.push(slice));
+        let slice_iter = json_slice.split_inclusive_mut(object_separator);
+        if let (Some(selector), true) = (selector, config.is_sampling()) {
+            return add_sampled_selected_records(slice_iter, builder, selector, config);
+        }
+        if config.is_sampling() || config.stable_records.is_some() {
+            return add_schema_from_records(sample_records(slice_iter, config), builder, selector, config.stable_records);
+        }
+        slice_iter.for_each(|slice| object_slices.push(slice));
         let object_iter = object_slices.into_par_iter();
-        add_schema_from_object_par_iter(object_iter, builder);
+        add_schema_from_object_par_iter(object_iter, builder, selector)
     }
 }
 
-fn add_schema_from_object_par_iter<'a>(object_iter: impl ParallelIterator<Item = &'a mut [u8]>, builder: &mut SchemaBuilder) {
+/// Add the records to the schema builder in parallel, or if `stable_records` is given, in
+/// chunks of that many records until a chunk doesn't change the structure of the schema, so
+/// that the rest of the records are never parsed
+fn add_schema_from_records<'a>(
+    records: impl Iterator<Item = &'a mut [u8]>,
+    builder: &mut SchemaBuilder,
+    selector: Option<&Selector>,
+    stable_records: Option<usize>,
+) -> Result<(), BuildError> {
+    let Some(stable_records) = stable_records else {
+        let object_slices: Vec<&mut [u8]> = records.collect();
+        return add_schema_from_object_par_iter(object_slices.into_par_iter(), builder, selector);
+    };
+    let mut records = records.peekable();
+    while records.peek().is_some() {
+        let chunk: Vec<&mut [u8]> = records.by_ref().take(stable_records.max(1)).collect();
+        let structure_changes = builder.structure_changes();
+        add_schema_from_object_par_iter(chunk.into_par_iter(), builder, selector)?;
+        if builder.structure_changes() == structure_changes {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Add the records the selector selects in the documents to the schema builder one document
+/// at a time, sampling them across the documents, until the maximum number of records is
+/// sampled. With `stable_records`, ingestion stops once that many sampled records in a row
+/// haven't changed the structure of the schema.
+fn add_sampled_selected_records<'a>(
+    documents: impl Iterator<Item = &'a mut [u8]>,
+    builder: &mut SchemaBuilder,
+    selector: &Selector,
+    config: &BuildConfig,
+) -> Result<(), BuildError> {
+    // the builder is left untouched if any of the documents can't be parsed
+    let mut sampled_builder = SchemaBuilder::with_shared_config(None, builder.shared_config());
+    let mut sampling = SelectedRecordSampling::new(config);
+    let mut structure_changes = 0;
+    let mut stable_since = 0;
+    for document in documents {
+        let document = trim_to_object(document);
+        if document.is_empty() {
+            continue;
+        }
+        if sampling.is_done() {
+            break;
+        }
+        build_selected_records_schema(&mut sampled_builder, document, Some(selector), Some(&mut sampling))?;
+        if config.stable_records.is_some_and(|stable_records| sampling.sampled - stable_since >= stable_records.max(1)) {
+            if sampled_builder.structure_changes() == structure_changes {
+                break;
+            }
+            structure_changes = sampled_builder.structure_changes();
+            stable_since = sampling.sampled;
+        }
+    }
+    builder.add_builder(&sampled_builder);
+    Ok(())
+}
+
+fn add_schema_from_object_par_iter<'a>(
+    object_iter: impl ParallelIterator<Item = &'a mut [u8]>,
+    builder: &mut SchemaBuilder,
+    selector: Option<&Selector>,
+) -> Result<(), BuildError> {
+    // every chunk builder infers the schema with the same configuration as the target builder
+    let config = builder.shared_config();
     let combined_builder = object_iter
-    .fold(
-        || SchemaBuilder::new(None),
+    .try_fold(
+        || SchemaBuilder::with_shared_config(None, config.clone()),
         |mut chunk_builder, object| {
             let trimmed_object = trim_to_object(object);
             if trimmed_object.is_empty() {
-                return chunk_builder;
+                return Ok(chunk_builder);
             }
-            build_single_json_object_schema(&mut chunk_builder, trimmed_object);
-            chunk_builder
+            build_selected_records_schema(&mut chunk_builder, trimmed_object, selector, None)?;
+            Ok(chunk_builder)
         },
-    ).reduce_with(|mut builder1, builder2| {
-        builder1.add_schema(builder2.to_schema());
-        builder1
-    }).unwrap_or(SchemaBuilder::new(None));
+    ).try_reduce_with(|mut builder1, builder2| {
+        builder1.add_builder(&builder2);
+        Ok(builder1)
+    }).unwrap_or_else(|| Ok(SchemaBuilder::with_shared_config(None, config.clone())))?;
 
-    builder.add_schema(combined_builder.to_schema());
+    builder.add_builder(&combined_builder);
+    Ok(())
 }
 
 /// trim the whitespace and non-JSON object characters from the start and end of the data
@@ -132,6 +421,38 @@ fn trim_to_object(data: &mut [u8]) -> &mut [u8] {
     &mut data[start..end]
 }
 
+/// Check if the arrays and objects in the JSON data are nested deeper than the maximum depth,
+/// without parsing it, so that the nesting can be checked before anything recurses into it
+fn exceeds_nesting_depth(data: &[u8], max_depth: usize) -> bool {
+    let mut depth: usize = 0;
+    let mut in_string = false;
+    let mut is_escaped = false;
+    for &byte in data {
+        if in_string {
+            if is_escaped {
+                is_escaped = false;
+            } else if byte == b'\\' {
+                is_escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max_depth {
+                    return true;
+                }
+            },
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+    }
+    false
+}
+
 /// Check if the data is a JSON object array, this function assumes that the data is trimmed
 /// with `trim_to_object` before calling it
 fn is_json_object_array(data: &[u8]) -> bool {
@@ -182,6 +503,13 @@ mod tests {
         assert_eq!(trimmed_data, expected_data);
     }
 
+    #[test]
+    fn test_exceeds_nesting_depth() {
+        let data = br#"{"a": [[1]], "b": "[[[[{{{{"}"#.to_vec();
+        assert_eq!(exceeds_nesting_depth(&data, 3), false);
+        assert_eq!(exceeds_nesting_depth(&data, 2), true);
+    }
+
     #[test]
     fn test_is_json_object_array() {
         let data = br#"[{"name": "John", "age": 30}]"#.to_vec();
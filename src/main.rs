@@ -2,6 +2,8 @@ use std::process;
 
 use clap::{ArgAction, Parser};
 use genson_rs::*;
+use regex::Regex;
+use serde_json::Value;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::mem;
 
@@ -23,6 +25,192 @@ struct Cli {
     /// you only care about the schema of the JSON objects inside of it
     ignore_outer_array: bool,
 
+    #[arg(short, long = "schema", value_name = "SCHEMA_FILE")]
+    /// Path to a JSON schema file to seed the schema with before the JSON objects are 
+    /// merged into it. Its descriptions, formats and constraints are kept. Can be 
+    /// specified multiple times.
+    schemas: Vec<String>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Track the observed string lengths, array lengths and object property counts, and
+    /// include them in the schema as "minLength"/"maxLength", "minItems"/"maxItems" and 
+    /// "minProperties"/"maxProperties"
+    size_constraints: bool,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Generalize string values (e.g. identifiers like "INV-000123") into a "pattern" 
+    /// made of character classes, no pattern is included if the values are too diverse
+    string_patterns: bool,
+
+    #[arg(long, default_value="0")]
+    /// Include up to this many distinct observed values of each string, number and 
+    /// boolean field in the schema as "examples"
+    max_examples: usize,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Collapse objects whose keys are all integers, UUIDs or dates into 
+    /// "additionalProperties" with the merged schema of their values
+    map_key_shapes: bool,
+
+    #[arg(long)]
+    /// Collapse objects with more distinct keys than this into "additionalProperties" 
+    /// with the merged schema of their values
+    map_key_threshold: Option<usize>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Describe the keys of the collapsed objects with a "propertyNames" pattern
+    map_property_names: bool,
+
+    #[arg(long = "pattern-property", value_name = "REGEX", value_parser = Regex::new)]
+    /// Infer the properties whose names match the regex as "patternProperties" instead 
+    /// of individual "properties", can be specified multiple times
+    pattern_properties: Vec<Regex>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Close every object to properties that were not observed with "additionalProperties": false
+    /// (or "unevaluatedProperties": false for JSON Schema draft 2019-09 and later)
+    strict_objects: bool,
+
+    #[arg(long = "open-object", value_name = "JSON_POINTER")]
+    /// Keep the object at the path open when --strict-objects is set, array items are 
+    /// addressed with "*" (e.g. "/items/*/metadata"), supports the same globs as --exclude-path
+    /// (e.g. "/**/metadata"), can be specified multiple times
+    open_objects: Vec<String>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Infer fixed-length arrays with items of different types (e.g. ["key", 3]) as tuples, 
+    /// arrays fall back to lists once their lengths or the types at a position vary
+    tuple_detection: bool,
+
+    #[arg(long)]
+    /// Must be one of "mixed", "type-array", "any-of", "openapi". How fields that can be 
+    /// null are represented: "mixed" (the default) adds "null" to the "type" of scalars but as 
+    /// an "anyOf" branch to objects and arrays, "type-array" always adds it to the "type", 
+    /// "any-of" always adds a {"type": "null"} branch, "openapi" marks them "nullable": true 
+    /// (and never emits type arrays, as OpenAPI 3.0 doesn't support them)
+    nullable_style: Option<String>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Emit "oneOf" instead of "anyOf" for unions whose branches are mutually exclusive by type
+    one_of: bool,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Infer one object schema per value of a discriminator property (auto-detected among 
+    /// conventional names like "type" and "kind"), emitted as a "oneOf" of the variants, 
+    /// which are referred to from the discriminator mapping with --nullable-style openapi
+    discriminated_unions: bool,
+
+    #[arg(long = "discriminator", value_name = "PROPERTY")]
+    /// Infer discriminated unions of objects tagged by this property, see --discriminated-unions
+    discriminator_property: Option<String>,
+
+    #[arg(long, value_name = "FRACTION")]
+    /// Mark a property as required when it's present in at least this fraction of the 
+    /// objects (e.g. 0.999), instead of only when it's present in all of them
+    required_threshold: Option<f64>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Annotate every field with the number of values observed ("x-genson-count"), the 
+    /// fraction of null values ("x-genson-null-ratio") and the number of values of each 
+    /// type ("x-genson-type-distribution")
+    stats_annotations: bool,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Infer floats without a fractional part (e.g. 1.0, or integers beyond 64 bits) as integers
+    integral_floats: bool,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Include the smallest "format" that fits the observed numbers: "int32" or "int64" 
+    /// for integers, "float" or "double" for other numbers
+    number_formats: bool,
+
+    #[arg(long)]
+    /// Must be one of "ignore", "annotate", "coerce". What's done with string fields whose 
+    /// values are all numbers or booleans (e.g. "42" or "true"): "annotate" adds the encoded 
+    /// type as "x-genson-encoded-type", "coerce" also infers the fields as the encoded type
+    encoded_scalars: Option<String>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Annotate numeric fields whose values are all Unix epoch timestamps with their unit, 
+    /// as "x-genson-timestamp": "epoch-s", "epoch-ms" or "epoch-us"
+    epoch_timestamps: bool,
+
+    #[arg(long = "timestamp-name-hint", value_name = "SUFFIX")]
+    /// Only detect epoch timestamps for the fields whose name ends with the suffix 
+    /// (e.g. "_at", "_ts" or "time"), can be specified multiple times
+    timestamp_name_hints: Vec<String>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Annotate numeric fields with the maximum number of digits of their values as written, 
+    /// as "x-genson-decimal": {"precision", "scale"} (e.g. 6 and 2 for "1234.50")
+    decimal_precision: bool,
+
+    #[arg(long, value_name = "MIN_SIZE")]
+    /// Move the subschemas that occur more than once, and have at least this many keywords, 
+    /// into "$defs" ("definitions" before draft 2019-09), and replace them with a "$ref"
+    extract_definitions: Option<usize>,
+
+    #[arg(long, action=ArgAction::SetTrue, default_value="false")]
+    /// Replace the objects nested in an object of the same shape (e.g. comment threads or 
+    /// org charts) with a recursive "$ref", instead of unrolling every level of nesting
+    recursive_structures: bool,
+
+    #[arg(long, value_name = "DEPTH")]
+    /// Only infer the schema down to this many levels of nesting below the root, the values 
+    /// nested deeper are emitted as an empty schema (or the placeholder)
+    max_depth: Option<usize>,
+
+    #[arg(long, value_name = "JSON")]
+    /// The schema emitted for the values nested deeper than the maximum depth 
+    /// (e.g. '{"description": "opaque"}')
+    max_depth_placeholder: Option<String>,
+
+    #[arg(long = "include-path", value_name = "JSON_POINTER")]
+    /// Only infer the schema of the nodes at the JSON pointer (e.g. "/payload/**"), along with 
+    /// their ancestors and descendants. Array items, map values and pattern property values 
+    /// are addressed with "*", segments can be globs (e.g. "internal_*"), "**" matches any 
+    /// number of segments, and pointers starting with "!" are excluded instead. Can be 
+    /// specified multiple times.
+    include_paths: Vec<String>,
+
+    #[arg(long = "exclude-path", value_name = "JSON_POINTER")]
+    /// Leave the nodes at the JSON pointer (e.g. "/debug" or "/items/*/internal_*") out of 
+    /// the schema, along with their descendants. Can be specified multiple times.
+    exclude_paths: Vec<String>,
+
+    #[arg(long, value_name = "EXPRESSION")]
+    /// Build the schema from the records selected in each JSON document by a jq-style 
+    /// expression (e.g. ".data.items[]"), which supports fields, array indices and "[]" 
+    /// to iterate over arrays and objects. The sampling options apply to the selected records.
+    selector: Option<String>,
+
+    #[arg(long, default_value = "0")]
+    /// The number of records to skip from the start of each file
+    skip: usize,
+
+    #[arg(long, value_name = "N")]
+    /// The maximum number of records of each file to build the schema from, after skipping 
+    /// and sampling. The rest of the records are not parsed.
+    max_records: Option<usize>,
+
+    #[arg(long, value_name = "N")]
+    /// Only build the schema from every Nth record of each file
+    sample_every: Option<usize>,
+
+    #[arg(long, value_name = "FRACTION")]
+    /// Build the schema from a random sample of this fraction of the records (e.g. 0.01), 
+    /// which is the same for the same --seed
+    sample_rate: Option<f64>,
+
+    #[arg(long, default_value = "0")]
+    /// The seed of the random sampling of --sample-rate
+    seed: u64,
+
+    #[arg(long, value_name = "N")]
+    /// Stop reading each file once N consecutive records have not changed the structure of 
+    /// the schema, and report the number of records that were read
+    stop_when_stable: Option<usize>,
+
     /// Path to the JSON file(s) to generate the schema from. The generated schema will 
     /// accomodate all the JSON objects in the file(s).
     json_files: Option<Vec<String>>,
@@ -46,39 +234,153 @@ fn get_delimiter(cli: &Cli) -> Option<u8> {
     }
 }
 
+/// Get the nullable style from the CLI arguments
+fn get_nullable_style(cli: &Cli) -> NullableStyle {
+    match cli.nullable_style.as_deref() {
+        None | Some("mixed") => NullableStyle::Mixed,
+        Some("type-array") => NullableStyle::TypeArray,
+        Some("any-of") => NullableStyle::AnyOf,
+        Some("openapi") => NullableStyle::OpenApi,
+        Some(style) => {
+            panic!("Invalid nullable style: {}, must be one of \"mixed\", \"type-array\", \"any-of\", \"openapi\"", style);
+        },
+    }
+}
+
+/// Get the handling of string encoded numbers and booleans from the CLI arguments
+fn get_encoded_scalars(cli: &Cli) -> EncodedScalars {
+    match cli.encoded_scalars.as_deref() {
+        None | Some("ignore") => EncodedScalars::Ignore,
+        Some("annotate") => EncodedScalars::Annotate,
+        Some("coerce") => EncodedScalars::Coerce,
+        Some(mode) => {
+            panic!("Invalid encoded scalars mode: {}, must be one of \"ignore\", \"annotate\", \"coerce\"", mode);
+        },
+    }
+}
+
+/// Parse the selector of the records to build the schema from
+fn get_selector(cli: &Cli) -> Option<Selector> {
+    cli.selector.as_ref().map(|expression| {
+        Selector::parse(expression).unwrap_or_else(|error| panic!("{}", error))
+    })
+}
+
+/// Parse the placeholder for the values nested deeper than the maximum depth
+fn get_max_depth_placeholder(cli: &Cli) -> Option<Value> {
+    cli.max_depth_placeholder.as_ref().map(|placeholder| {
+        match serde_json::from_str::<Value>(placeholder) {
+            Ok(schema @ Value::Object(_)) => schema,
+            _ => panic!("Invalid max depth placeholder: {}, must be a JSON object", placeholder),
+        }
+    })
+}
+
+/// Read the seed schemas from the files given with --schema
+fn get_seed_schemas(cli: &Cli) -> Vec<Value> {
+    cli.schemas.iter().map(|file_path| {
+        let schema = std::fs::read(file_path).unwrap();
+        match serde_json::from_slice::<Value>(&schema) {
+            Ok(schema @ Value::Object(_)) => schema,
+            _ => panic!("Invalid schema file: {}, must contain a JSON object", file_path),
+        }
+    }).collect()
+}
+
+/// Get the schema inference configuration from the CLI arguments
+fn get_schema_config(cli: &Cli) -> SchemaConfig {
+    SchemaConfig {
+        size_constraints: cli.size_constraints,
+        string_patterns: cli.string_patterns,
+        max_examples: cli.max_examples,
+        map_key_shapes: cli.map_key_shapes,
+        map_key_threshold: cli.map_key_threshold,
+        map_property_names: cli.map_property_names,
+        pattern_properties: cli.pattern_properties.clone(),
+        strict_objects: cli.strict_objects,
+        open_objects: cli.open_objects.clone(),
+        tuple_detection: cli.tuple_detection,
+        nullable_style: get_nullable_style(cli),
+        one_of: cli.one_of,
+        discriminated_unions: cli.discriminated_unions,
+        discriminator_property: cli.discriminator_property.clone(),
+        required_threshold: cli.required_threshold,
+        stats_annotations: cli.stats_annotations,
+        integral_floats: cli.integral_floats,
+        number_formats: cli.number_formats,
+        encoded_scalars: get_encoded_scalars(cli),
+        epoch_timestamps: cli.epoch_timestamps,
+        timestamp_name_hints: cli.timestamp_name_hints.clone(),
+        decimal_precision: cli.decimal_precision,
+        extract_definitions: cli.extract_definitions,
+        recursive_structures: cli.recursive_structures,
+        max_depth: cli.max_depth,
+        max_depth_placeholder: get_max_depth_placeholder(cli),
+        include_paths: cli.include_paths.clone(),
+        exclude_paths: cli.exclude_paths.clone(),
+        ..Default::default()
+    }
+}
+
 /// Generate a JSON Schema from a JSON file
 fn build_schema(
     builder: &mut SchemaBuilder, file_path: &str, config: BuildConfig
-) {
+) -> Result<(), String> {
     let mut object_slice = std::fs::read(file_path).unwrap();
-    build_json_schema(builder, &mut object_slice, &config);
+    let result = try_build_json_schema(builder, &mut object_slice, &config);
     // NOTE: avoid dropping the object_slice to improve performance
     // the effect is more siginificant for larger JSON files
     mem::forget(object_slice);
+    result.map(|_| ()).map_err(|error| format!("{}: {}", file_path, error))
 }
 
 fn main() {
     let cli = Cli::parse();
     let delimiter = get_delimiter(&cli);
+    let selector = get_selector(&cli);
+    let schema_config = get_schema_config(&cli);
+    let seed_schemas = get_seed_schemas(&cli);
+
+    let new_builder = || SchemaBuilder::with_config(Some("AUTO"), schema_config.clone());
 
     if let Some(json_files) = cli.json_files.as_deref() {
         // parallelize the schema building process for multiple JSON files
         let aggregated_builder = json_files.par_iter()
-            .fold(
-            || get_builder(Some("AUTO")), 
+            .try_fold(
+            new_builder, 
             |mut builder, file_path| {
                 build_schema(&mut builder, file_path, BuildConfig {
                     delimiter,
                     ignore_outer_array: cli.ignore_outer_array,
-                });
-                return builder;
-            }).reduce(
-            || get_builder(Some("AUTO")),
+                    selector: selector.clone(),
+                    skip: cli.skip,
+                    max_records: cli.max_records,
+                    sample_every: cli.sample_every,
+                    sample_rate: cli.sample_rate,
+                    seed: cli.seed,
+                    stable_records: cli.stop_when_stable,
+                })?;
+                return Ok(builder);
+            }).try_reduce(
+            new_builder,
             |mut builder, other_builder| {
-                builder.add_schema(other_builder.to_schema());
-                return builder;
+                builder.add_builder(&other_builder);
+                return Ok(builder);
             });
+        let aggregated_builder = aggregated_builder.unwrap_or_else(|error: String| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        // the seed schemas are added once, and before the data, so that their counts aren't
+        // repeated and the data extends them (e.g. properties matching their "patternProperties" go there)
+        let mut seeded_builder = new_builder();
+        seed_schemas.iter().for_each(|schema| seeded_builder.add_schema(schema.clone()));
+        seeded_builder.add_builder(&aggregated_builder);
+        let aggregated_builder = seeded_builder;
 
+        if cli.stop_when_stable.is_some() {
+            eprintln!("The schema was built from {} records", aggregated_builder.object_count());
+        }
         let schema = aggregated_builder.to_schema();
         println!("{}", schema.to_string());
 
@@ -1,17 +1,134 @@
 use core::panic;
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use serde_json::{json, Value};
 use simd_json;
+use crate::config::{NullableStyle, SchemaConfig};
+use crate::lexeme::Lexeme;
+use crate::path::is_path_included;
 use crate::strategy::BasicSchemaStrategy;
 use crate::strategy::base::SchemaStrategy;
+use crate::strategy::encoded::{ENCODED_TYPE_KEYWORD, NATIVE_VALUES_KEYWORD};
+use crate::strategy::object::is_discriminator_detection_enabled;
 use crate::strategy::scalar::TypelessStrategy;
+use crate::strategy::stats::NodeStats;
+use crate::MAX_NESTING_DEPTH;
 
 /// Basic schema generator class. SchemaNode objects can be loaded
 /// up with existing schemas and objects before being serialized.
 #[derive(Debug)]
 pub struct SchemaNode {
-    active_strategies: Vec<BasicSchemaStrategy>
+    active_strategies: Vec<BasicSchemaStrategy>,
+    // only counted when stats annotations are enabled
+    stats: Option<NodeStats>,
+    context: NodeContext,
+}
+
+/// NodeContext describes where a SchemaNode sits in the schema being inferred. It
+/// is shared between the node and its strategies, which use it to create child nodes.
+#[derive(Debug, Clone)]
+pub struct NodeContext {
+    pub config: Arc<SchemaConfig>,
+    /// JSON pointer of the node from the root of the schema, the items of
+    /// list-style arrays are addressed with `ITEMS_PATH_SEGMENT`
+    pub path: String,
+    /// Number of nesting levels from the root of the schema, which is at depth 0
+    pub depth: usize,
+    /// If the node is left out of the schema by the path filters, along with its descendants
+    pub excluded: bool,
+    /// The number of changes to the structure of the schema, shared by all of its nodes
+    structure_changes: Arc<AtomicUsize>,
+}
+
+/// Path segment used for the items of list-style arrays
+pub const ITEMS_PATH_SEGMENT: &str = "*";
+
+impl NodeContext {
+    pub fn root(config: Arc<SchemaConfig>) -> Self {
+        NodeContext {
+            config,
+            path: String::new(),
+            depth: 0,
+            excluded: false,
+            structure_changes: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Create the context for a child node reached through the given property name,
+    /// tuple index or `ITEMS_PATH_SEGMENT`
+    pub fn child(&self, segment: &str) -> Self {
+        let segment = segment.replace('~', "~0").replace('/', "~1");
+        let path = format!("{}/{}", self.path, segment);
+        let excluded = self.excluded || (
+            self.config.has_path_filters()
+                && !is_path_included(&path, &self.config.include_paths, &self.config.exclude_paths)
+        );
+        NodeContext {
+            config: self.config.clone(),
+            path,
+            depth: self.depth + 1,
+            excluded,
+            structure_changes: self.structure_changes.clone(),
+        }
+    }
+
+    /// Get a copy of the context whose structure changes are counted separately, for the
+    /// temporary nodes that are merged into the node of this context
+    pub fn untracked(&self) -> Self {
+        NodeContext { structure_changes: Arc::new(AtomicUsize::new(0)), ..self.clone() }
+    }
+
+    /// Count a change to the structure of the schema, i.e. a new type, property or array
+    /// item, a property that is no longer required, or an integer that became a number.
+    /// Constraints and annotations aren't part of the structure.
+    pub fn mark_structure_changed(&self) {
+        self.structure_changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the number of changes to the structure of the schema so far
+    pub fn structure_changes(&self) -> usize {
+        self.structure_changes.load(Ordering::Relaxed)
+    }
+
+    /// Get the name of the property the node describes, where the items of an
+    /// array property are described by the name of the array property as well
+    pub fn field_name(&self) -> Option<String> {
+        self.path.split('/').rev()
+            .find(|segment| *segment != ITEMS_PATH_SEGMENT)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+    }
+
+    /// Check if the node is nested deeper than the maximum depth, in which case
+    /// nothing is inferred for it and it's emitted as the opaque placeholder. Nodes beyond
+    /// `MAX_NESTING_DEPTH` levels (including the root) are never inferred, so that adding
+    /// a deeply nested object or schema doesn't overflow the stack.
+    pub fn is_beyond_max_depth(&self) -> bool {
+        self.depth > self.max_depth()
+    }
+
+    /// Check if the children of the node are nested deeper than the maximum depth, in which
+    /// case they aren't tracked, as they'd all be emitted as the placeholder anyway
+    pub fn is_at_max_depth(&self) -> bool {
+        self.depth >= self.max_depth()
+    }
+
+    /// Get the schema emitted for the values nested deeper than the maximum depth
+    pub fn max_depth_placeholder(&self) -> Value {
+        self.config.max_depth_placeholder.clone().unwrap_or_else(|| json!({}))
+    }
+
+    fn max_depth(&self) -> usize {
+        self.config.max_depth.unwrap_or(usize::MAX).min(MAX_NESTING_DEPTH - 1)
+    }
+}
+
+/// The schema of a field that was only ever null in the OpenAPI nullable style. OpenAPI 3.0
+/// has no "null" type, so it's a nullable string whose only allowed value is null.
+fn openapi_null_schema() -> Value {
+    json!({"type": "string", "nullable": true, "enum": [null]})
 }
 
 /// DataType wraps around different types of schema data that can be added
@@ -28,38 +145,79 @@ pub enum DataType<'a> {
 }
 
 impl SchemaNode {
-    pub fn new() -> Self {
+    pub fn new(context: NodeContext) -> Self {
         SchemaNode {
-            active_strategies: vec![]
+            active_strategies: vec![],
+            stats: context.config.stats_annotations.then(NodeStats::default),
+            context,
         }
     }
 
+    /// Get the number of changes to the structure of the schema of this node and its
+    /// descendants so far, to tell if adding an object changed anything
+    pub fn structure_changes(&self) -> usize {
+        self.context.structure_changes()
+    }
+
     pub fn add_schema(&mut self, data: DataType) -> &mut Self {
-        let schema = match data {
+        if self.context.is_beyond_max_depth() || self.context.excluded {
+            return self;
+        }
+        let mut schema = match data {
             DataType::SchemaNode(node) => node.to_schema(),
             DataType::Schema(schema) => schema.clone(),
             _ => panic!("Invalid schema type")
         };
+        if let Some(stats) = self.stats.as_mut() {
+            stats.add_schema(&mut schema);
+        }
 
-        for subschema in SchemaNode::get_subschemas(&schema) {
+        for subschema in self.get_subschemas(&schema) {
             let active_strategy = self.get_or_create_strategy_for_schema(&subschema);
             SchemaNode::add_schema_or_object_to_strategy(active_strategy, DataType::Schema(&subschema));
         }
         self
     }
 
-    fn get_subschemas(schema: &Value) -> Vec<Value> {
+    fn get_subschemas(&self, schema: &Value) -> Vec<Value> {
+        let parses_nullable = self.context.config.nullable_style != NullableStyle::Mixed;
         if let Value::Object(schema) = schema {
-            if let Some(Value::Array(anyof)) = schema.get("anyOf") {
-                return anyof.iter().map(|t| SchemaNode::get_subschemas(t)).flatten().collect();
+            if parses_nullable && Value::Object(schema.clone()) == openapi_null_schema() {
+                return vec![json!({"type": "null"})];
+            }
+            if parses_nullable && schema.get("nullable") == Some(&Value::Bool(true)) {
+                let mut new_schema = schema.clone();
+                new_schema.remove("nullable");
+                let mut subschemas = if new_schema.is_empty() {
+                    vec![]
+                } else {
+                    self.get_subschemas(&Value::Object(new_schema))
+                };
+                subschemas.push(json!({"type": "null"}));
+                return subschemas;
+            }
+            else if let Some(Value::Array(anyof)) = schema.get("anyOf") {
+                return anyof.iter().map(|t| self.get_subschemas(t)).flatten().collect();
+            }
+            else if let (true, Some(Value::Array(oneof))) = (self.parses_one_of(), schema.get("oneOf")) {
+                return oneof.iter().flat_map(|t| self.get_subschemas(t)).collect();
             }
             else if let Some(Value::Array(types)) = schema.get("type") {
                 return types.iter().map(|t| {
+                    if parses_nullable && t == "null" {
+                        // the other keywords only describe the non-null types
+                        return json!({"type": "null"});
+                    }
                     let mut new_schema = schema.clone();
                     new_schema["type"] = t.clone();
                     return Value::Object(new_schema);
                 }).collect();
             }
+            else if schema.contains_key(NATIVE_VALUES_KEYWORD) {
+                let mut coerced_schema = schema.clone();
+                coerced_schema.remove(NATIVE_VALUES_KEYWORD);
+                return vec![json!({"type": schema["type"]}), Value::Object(coerced_schema)];
+            }
             else {
                 return vec![Value::Object(schema.clone())];
             }
@@ -67,20 +225,56 @@ impl SchemaNode {
         return vec![schema.clone()];
     }
 
+    /// Check if the "oneOf" of a schema is split into its subschemas, which is only the
+    /// case when "oneOf" can be generated by the configuration
+    fn parses_one_of(&self) -> bool {
+        self.context.config.one_of || is_discriminator_detection_enabled(&self.context.config)
+    }
+
+    /// Check if the node has accomodated more than one type of schema or object
+    pub fn has_multiple_types(&self) -> bool {
+        self.active_strategies.len() > 1
+    }
+
     /// Modify the schema to accomodate the object.
     pub fn add_object(&mut self, data: DataType) -> &mut Self {
         let object = match data {
             DataType::Object(obj) => obj,
             _ => panic!("Invalid object type")
         };
+        if self.context.is_beyond_max_depth() || self.context.excluded {
+            return self;
+        }
 
+        if let Some(stats) = self.stats.as_mut() {
+            stats.add_object(object);
+        }
         let active_strategy = self.get_or_create_strategy_for_object(&object);
         SchemaNode::add_schema_or_object_to_strategy(active_strategy, DataType::Object(object));
         self
     }
 
+    /// Route the text of the object that was last added to the strategies, which only
+    /// accept the lexemes of the kind of values they describe
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        self.active_strategies.iter_mut().for_each(|strategy| strategy.add_lexeme(lexeme));
+    }
+
     /// Convert the current schema node to a JSON schema
     pub fn to_schema(&self) -> Value {
+        if self.context.is_beyond_max_depth() {
+            return self.context.max_depth_placeholder();
+        }
+        let mut schema = self.strategies_to_schema();
+        if let Some(stats) = &self.stats {
+            stats.to_schema(&mut schema);
+        }
+        schema
+    }
+
+    /// Combine the schemas of the active strategies into one schema
+    fn strategies_to_schema(&self) -> Value {
+        let nullable_style = self.context.config.nullable_style;
         let mut scalar_types: HashSet<String> = HashSet::new();
         let mut generated_schemas: Vec<Value> = vec![];
 
@@ -98,10 +292,27 @@ impl SchemaNode {
             }
         });
 
+        // coerced strings already describe the values natively of the same type
+        generated_schemas.iter_mut()
+            .filter(|schema| schema.get(ENCODED_TYPE_KEYWORD).is_some())
+            .for_each(|schema| {
+                if schema["type"].as_str().is_some_and(|t| scalar_types.remove(t)) {
+                    schema[NATIVE_VALUES_KEYWORD] = true.into();
+                }
+            });
+
+        // unless the style is mixed, null gets added to the schema of the other types at the end
+        let is_nullable = nullable_style != NullableStyle::Mixed && scalar_types.remove("null");
+
         if scalar_types.len() > 0 {
             if scalar_types.len() == 1 {
                 let scalar_type = scalar_types.iter().next().unwrap();
                 generated_schemas.push(json!({"type": scalar_type}));
+            } else if nullable_style == NullableStyle::OpenApi {
+                // OpenAPI 3.0 has no type arrays, every type is a branch of the union
+                let mut scalar_type_list: Vec<&String> = scalar_types.iter().collect();
+                scalar_type_list.sort();
+                scalar_type_list.into_iter().for_each(|scalar_type| generated_schemas.push(json!({"type": scalar_type})));
             } else {
                 let mut scalar_type_list: Vec<String> = scalar_types.iter().map(|s| s.to_string()).collect::<Vec<_>>();
                 scalar_type_list.sort();
@@ -109,15 +320,97 @@ impl SchemaNode {
             }
         }
 
+        if is_nullable {
+            return self.to_nullable_schema(generated_schemas, nullable_style);
+        }
+
         if generated_schemas.len() == 1 {
             return generated_schemas[0].clone();
         } else if generated_schemas.len() > 0 {
-            return json!({"anyOf": generated_schemas});
+            return self.to_union_schema(generated_schemas);
         } else {
             return json!({});
         }
     }
 
+    /// Combine the schemas of the non-null types into a schema that also accepts null,
+    /// represented according to the nullable style
+    fn to_nullable_schema(&self, mut generated_schemas: Vec<Value>, nullable_style: NullableStyle) -> Value {
+        if generated_schemas.is_empty() {
+            return match nullable_style {
+                NullableStyle::OpenApi => openapi_null_schema(),
+                _ => json!({"type": "null"}),
+            };
+        }
+
+        match nullable_style {
+            NullableStyle::TypeArray if generated_schemas.len() == 1 => {
+                let mut schema = generated_schemas.pop().unwrap();
+                if let Some(Value::Array(variants)) = schema.get_mut("oneOf") {
+                    variants.push(json!({"type": "null"}));
+                    return schema;
+                }
+                if schema.get("type").is_none() {
+                    // a schema without a type accepts null already
+                    return schema;
+                }
+                schema["type"] = match schema["type"].take() {
+                    Value::Array(mut types) => {
+                        types.push("null".into());
+                        Value::Array(types)
+                    },
+                    other => json!([other, "null"]),
+                };
+                schema
+            },
+            NullableStyle::OpenApi => {
+                // "nullable" only applies to the "type" next to it, so every branch is nullable,
+                // and the branches are never exclusive, as null is valid against all of them
+                generated_schemas.iter_mut().for_each(|schema| schema["nullable"] = true.into());
+                if generated_schemas.len() == 1 {
+                    generated_schemas.pop().unwrap()
+                } else {
+                    json!({"anyOf": generated_schemas})
+                }
+            },
+            _ => {
+                generated_schemas.push(json!({"type": "null"}));
+                self.to_union_schema(generated_schemas)
+            },
+        }
+    }
+
+    /// Combine the schemas into an "anyOf", or into a "oneOf" when it's enabled and
+    /// the schemas are provably mutually exclusive
+    fn to_union_schema(&self, schemas: Vec<Value>) -> Value {
+        if self.context.config.one_of && SchemaNode::are_disjoint_by_type(&schemas) {
+            json!({"oneOf": schemas})
+        } else {
+            json!({"anyOf": schemas})
+        }
+    }
+
+    /// Check if no value can be valid against more than one of the schemas, because
+    /// every schema has a "type" and none of the types are shared between them
+    fn are_disjoint_by_type(schemas: &[Value]) -> bool {
+        let mut seen_types: HashSet<&str> = HashSet::new();
+        for schema in schemas {
+            let types: Vec<&str> = match &schema["type"] {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                _ => return false,
+            };
+            for t in types {
+                // integers are numbers as well
+                let t = if t == "integer" { "number" } else { t };
+                if !seen_types.insert(t) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
     /// Get the current active strategy for the object, if not found create a new one.
     fn get_or_create_strategy_for_object(&mut self, object: &simd_json::BorrowedValue) -> &mut BasicSchemaStrategy {
         if let Some(idx) = self.get_strategy_for_kind(DataType::Object(object)) {
@@ -148,7 +441,7 @@ impl SchemaNode {
     }
 
     fn create_strategy_for_kind(&mut self, schema_or_object: DataType) -> Option<&mut BasicSchemaStrategy> {
-        if let Some(mut strategy) = SchemaNode::create_strategy_for_schema_or_object(&schema_or_object) {
+        if let Some(mut strategy) = SchemaNode::create_strategy_for_schema_or_object(&schema_or_object, &self.context) {
             if let Some(last_strategy) = self.active_strategies.last() {
                 // if the last strategy is a typeless strategy, incorporate it into the newly created strategy
                 if let BasicSchemaStrategy::Typeless(typeless) = last_strategy {
@@ -159,6 +452,7 @@ impl SchemaNode {
                 }
             }
             self.active_strategies.push(strategy);
+            self.context.mark_structure_changed();
             return Some(self.active_strategies.last_mut().unwrap());
         }
 
@@ -169,6 +463,7 @@ impl SchemaNode {
             if TypelessStrategy::match_schema(schema) {
                 if self.active_strategies.is_empty() {
                     self.active_strategies.push(BasicSchemaStrategy::Typeless(TypelessStrategy::new()));
+                    self.context.mark_structure_changed();
                 }
                 let first_strategy = self.active_strategies.first_mut().unwrap();
                 return Some(first_strategy);
@@ -186,10 +481,12 @@ impl SchemaNode {
     }
 
     /// Create a strategy for a schema or object based on which strategy it matches.
-    fn create_strategy_for_schema_or_object(schema_or_object: &DataType) -> Option<BasicSchemaStrategy> {
+    fn create_strategy_for_schema_or_object(
+        schema_or_object: &DataType, context: &NodeContext
+    ) -> Option<BasicSchemaStrategy> {
         match schema_or_object {
-            DataType::Object(obj) => BasicSchemaStrategy::new_for_object(obj),
-            DataType::Schema(schema) => BasicSchemaStrategy::new_for_schema(schema),
+            DataType::Object(obj) => BasicSchemaStrategy::new_for_object(obj, context),
+            DataType::Schema(schema) => BasicSchemaStrategy::new_for_schema(schema, context),
             _ => None
         }
     }
@@ -202,3 +499,20 @@ impl SchemaNode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemas_sharing_a_type_should_not_be_disjoint() {
+        assert!(SchemaNode::are_disjoint_by_type(&[
+            json!({"type": "object"}), json!({"type": ["string", "null"]}), json!({"type": "integer"}),
+        ]));
+        assert!(!SchemaNode::are_disjoint_by_type(&[
+            json!({"type": "object"}), json!({"type": ["string", "object"]}),
+        ]));
+        assert!(!SchemaNode::are_disjoint_by_type(&[json!({"type": "integer"}), json!({"type": "number"})]));
+        assert!(!SchemaNode::are_disjoint_by_type(&[json!({"type": "string"}), json!({"enum": [1]})]));
+    }
+}
@@ -0,0 +1,148 @@
+// Matches any number of path segments, including none
+const ANY_SEGMENTS: &str = "**";
+// Marks an include pattern as an exclude pattern
+const NEGATION_PREFIX: char = '!';
+
+/// Check if the node at the JSON pointer is inferred according to the include and exclude
+/// patterns. A node is left out when an exclude pattern matches it (or one of its ancestors),
+/// and when include patterns are given, a node is only inferred if an include pattern matches
+/// it, one of its ancestors, or one of its possible descendants. Include patterns that start
+/// with "!" are exclude patterns.
+///
+/// Patterns are JSON pointers whose segments can be globs, where "*" matches any part of a
+/// segment (e.g. "internal_*"), and a "**" segment matches any number of segments.
+pub fn is_path_included(path: &str, include_patterns: &[String], exclude_patterns: &[String]) -> bool {
+    let path = split_pointer(path);
+    let is_excluded = exclude_patterns.iter()
+        .map(String::as_str)
+        .chain(include_patterns.iter().filter_map(|pattern| pattern.strip_prefix(NEGATION_PREFIX)))
+        .any(|pattern| matches_subtree(&split_pointer(pattern), &path));
+    if is_excluded {
+        return false;
+    }
+
+    let mut include_patterns = include_patterns.iter()
+        .filter(|pattern| !pattern.starts_with(NEGATION_PREFIX))
+        .map(|pattern| split_pointer(pattern))
+        .peekable();
+    include_patterns.peek().is_none() || include_patterns.any(|pattern| {
+        matches_subtree(&pattern, &path) || matches_ancestor(&pattern, &path)
+    })
+}
+
+/// Check if any of the patterns matches the node at the JSON pointer itself, with the same
+/// glob syntax as the include and exclude patterns
+pub fn matches_any_path(path: &str, patterns: &[String]) -> bool {
+    let path = split_pointer(path);
+    patterns.iter().any(|pattern| matches_path(&split_pointer(pattern), &path))
+}
+
+fn split_pointer(pointer: &str) -> Vec<&str> {
+    pointer.split('/').skip(1).collect()
+}
+
+/// Check if the pattern matches the path or any of its ancestors
+fn matches_subtree(pattern: &[&str], path: &[&str]) -> bool {
+    (0..=path.len()).any(|len| matches_path(pattern, &path[..len]))
+}
+
+/// Check if the path is an ancestor of a path the pattern could match
+fn matches_ancestor(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some((&ANY_SEGMENTS, _)), Some(_)) => true,
+        (Some((pattern_segment, pattern)), Some((segment, path))) => {
+            matches_segment(pattern_segment, segment) && matches_ancestor(pattern, path)
+        },
+    }
+}
+
+fn matches_path(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (None, None) => true,
+        (Some((&ANY_SEGMENTS, rest)), _) => {
+            // either stop matching segments with "**", or match one more
+            matches_path(rest, path) || (!path.is_empty() && matches_path(pattern, &path[1..]))
+        },
+        (Some((pattern_segment, pattern)), Some((segment, path))) => {
+            matches_segment(pattern_segment, segment) && matches_path(pattern, path)
+        },
+        _ => false,
+    }
+}
+
+/// Check if the segment matches the glob, where "*" matches any (possibly empty) part of it
+fn matches_segment(glob: &str, segment: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = segment.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // the glob has no wildcard
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn test_matches_segment() {
+        assert!(matches_segment("*", "anything"));
+        assert!(matches_segment("internal_*", "internal_id"));
+        assert!(matches_segment("*_at", "created_at"));
+        assert!(matches_segment("a*b*c", "abc"));
+        assert!(!matches_segment("internal_*", "id"));
+        assert!(!matches_segment("a*a", "a"));
+        assert!(!matches_segment("id", "ids"));
+    }
+
+    #[test]
+    fn test_is_path_included_with_exclude_patterns() {
+        let exclude = patterns(&["/debug", "/items/*/internal_*"]);
+        assert!(is_path_included("", &[], &exclude));
+        assert!(!is_path_included("/debug", &[], &exclude));
+        assert!(!is_path_included("/debug/trace", &[], &exclude));
+        assert!(is_path_included("/items/*/id", &[], &exclude));
+        assert!(!is_path_included("/items/*/internal_id", &[], &exclude));
+    }
+
+    #[test]
+    fn test_is_path_included_with_include_patterns() {
+        let include = patterns(&["/payload/**", "/user/name", "!/payload/secret"]);
+        assert!(is_path_included("", &include, &[]));
+        assert!(is_path_included("/payload", &include, &[]));
+        assert!(is_path_included("/payload/a/b", &include, &[]));
+        assert!(!is_path_included("/payload/secret", &include, &[]));
+        assert!(is_path_included("/user", &include, &[]));
+        assert!(is_path_included("/user/name/first", &include, &[]));
+        assert!(!is_path_included("/user/email", &include, &[]));
+        assert!(!is_path_included("/debug", &include, &[]));
+    }
+
+    #[test]
+    fn test_matches_any_path() {
+        let open = patterns(&["/payload", "/items/*/meta*", "/**/extra"]);
+        assert!(matches_any_path("/payload", &open));
+        assert!(!matches_any_path("/payload/nested", &open));
+        assert!(matches_any_path("/items/*/metadata", &open));
+        assert!(matches_any_path("/extra", &open));
+        assert!(matches_any_path("/a/b/extra", &open));
+        assert!(!matches_any_path("", &open));
+    }
+}
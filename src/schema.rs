@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::MAX_NESTING_DEPTH;
+
+// Keywords whose value is a subschema
+const SUBSCHEMA_KEYWORDS: [&str; 4] = ["additionalProperties", "items", "not", "propertyNames"];
+// Keywords whose value is an array of subschemas
+const SUBSCHEMA_ARRAY_KEYWORDS: [&str; 5] = ["items", "prefixItems", "anyOf", "oneOf", "allOf"];
+// Keywords whose value is an object of subschemas
+const SUBSCHEMA_MAP_KEYWORDS: [&str; 4] = ["properties", "patternProperties", "$defs", "definitions"];
+// Keywords that hold the definitions referred to by "$ref"
+const DEFINITIONS_KEYWORDS: [&str; 2] = ["$defs", "definitions"];
+// The number of times a recursive reference is expanded while inlining it
+const MAX_REFERENCE_EXPANSIONS: usize = 2;
+
+/// Call the visitor on the schema and then recursively on all of its subschemas. Only the
+/// keywords that hold subschemas are traversed, so data like "examples" is left untouched.
+pub fn visit_schemas_mut(schema: &mut Value, visitor: &mut impl FnMut(&mut Value)) {
+    visitor(schema);
+    for_each_subschema_mut(schema, &mut |_, subschema| visit_schemas_mut(subschema, visitor));
+}
+
+/// Call the visitor on each direct subschema of the schema, along with its name when it's
+/// held by an object of subschemas (e.g. the property name for "properties")
+fn for_each_subschema_mut(schema: &mut Value, visitor: &mut impl FnMut(Option<&str>, &mut Value)) {
+    let Value::Object(schema) = schema else {
+        return;
+    };
+    SUBSCHEMA_KEYWORDS.iter().for_each(|keyword| {
+        if let Some(subschema @ Value::Object(_)) = schema.get_mut(*keyword) {
+            visitor(None, subschema);
+        }
+    });
+    SUBSCHEMA_ARRAY_KEYWORDS.iter().for_each(|keyword| {
+        if let Some(Value::Array(subschemas)) = schema.get_mut(*keyword) {
+            subschemas.iter_mut().for_each(|subschema| visitor(None, subschema));
+        }
+    });
+    SUBSCHEMA_MAP_KEYWORDS.iter().for_each(|keyword| {
+        if let Some(Value::Object(subschemas)) = schema.get_mut(*keyword) {
+            subschemas.iter_mut().for_each(|(name, subschema)| visitor(Some(name), subschema));
+        }
+    });
+}
+
+/// Check if the schema URI refers to draft 2019-09 or a later version of JSON Schema,
+/// e.g. "https://json-schema.org/draft/2020-12/schema"
+pub fn is_draft_2019_09_or_later(schema_uri: &str) -> bool {
+    schema_uri.split_once("/draft/")
+        .and_then(|(_, version)| version.get(..7))
+        .is_some_and(|version| version >= "2019-09")
+}
+
+/// A subschema that may be hoisted into the definitions
+struct Candidate {
+    schema: Value,
+    count: usize,
+    size: usize,
+    // the name of the property (or other named subschema) it was first found under
+    name: Option<String>,
+}
+
+/// Hoist the subschemas that occur more than once, and have at least `min_size` keywords counted
+/// recursively, into the `definitions_keyword` ("$defs" or "definitions") of the root schema, and
+/// replace every occurrence with a "$ref" to the definition. The largest subschemas are hoisted
+/// first, and the definitions are named after the property they were first found under.
+pub fn extract_definitions(schema: &mut Value, definitions_keyword: &str, min_size: usize) {
+    loop {
+        let mut candidates: HashMap<String, Candidate> = HashMap::new();
+        for_each_subschema_mut(schema, &mut |name, subschema| {
+            collect_candidates(subschema, name, &mut candidates);
+        });
+        let Some((_, candidate)) = candidates.into_iter()
+            .filter(|(_, candidate)| candidate.count > 1 && candidate.size >= min_size)
+            // the serialized schema breaks ties between equally large subschemas deterministically
+            .max_by(|(key1, c1), (key2, c2)| c1.size.cmp(&c2.size).then_with(|| key2.cmp(key1))) else {
+            return;
+        };
+
+        let definitions = schema.as_object_mut().unwrap()
+            .entry(definitions_keyword)
+            .or_insert_with(|| json!({}));
+        let name = unique_definition_name(definitions.as_object().unwrap(), candidate.name.as_deref().unwrap_or("definition"));
+        let reference = json!({"$ref": format!("#/{}/{}", definitions_keyword, escape_pointer_segment(&name))});
+        for_each_subschema_mut(schema, &mut |_, subschema| {
+            replace_subschemas(subschema, &candidate.schema, &reference);
+        });
+        schema[definitions_keyword][name] = candidate.schema;
+    }
+}
+
+fn collect_candidates(schema: &mut Value, name: Option<&str>, candidates: &mut HashMap<String, Candidate>) {
+    // references are never hoisted, as that would only add another level of indirection
+    if schema.is_object() && schema.get("$ref").is_none() {
+        let candidate = candidates.entry(schema.to_string()).or_insert_with(|| Candidate {
+            schema: schema.clone(),
+            count: 0,
+            size: schema_size(schema),
+            name: name.map(str::to_string),
+        });
+        candidate.count += 1;
+    }
+    for_each_subschema_mut(schema, &mut |subschema_name, subschema| {
+        // unnamed subschemas (e.g. the "items" of an array) are named after their parent
+        collect_candidates(subschema, subschema_name.or(name), candidates);
+    });
+}
+
+fn replace_subschemas(schema: &mut Value, target: &Value, replacement: &Value) {
+    if schema == target {
+        *schema = replacement.clone();
+        return;
+    }
+    for_each_subschema_mut(schema, &mut |_, subschema| replace_subschemas(subschema, target, replacement));
+}
+
+/// The number of keywords of the schema, including the ones of its subschemas
+fn schema_size(schema: &Value) -> usize {
+    match schema {
+        Value::Object(keywords) => keywords.values().map(|value| 1 + schema_size(value)).sum(),
+        Value::Array(values) => values.iter().map(schema_size).sum(),
+        _ => 0,
+    }
+}
+
+/// Get the name for a new definition, which is the base name with a numeric suffix
+/// when a definition with the base name already exists
+fn unique_definition_name(definitions: &Map<String, Value>, base_name: &str) -> String {
+    let mut name = base_name.to_string();
+    let mut suffix = 1;
+    while definitions.contains_key(&name) {
+        suffix += 1;
+        name = format!("{}{}", base_name, suffix);
+    }
+    name
+}
+
+fn escape_pointer_segment(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+/// Move the variants of the unions with an OpenAPI "discriminator" into the `definitions_keyword`
+/// of the root schema, named after the discriminator value they have as their single "enum"
+/// value, and add the "mapping" of the values to the references that replace the variants.
+pub fn extract_discriminator_variants(schema: &mut Value, definitions_keyword: &str) {
+    let Value::Object(root) = schema else {
+        return;
+    };
+    let mut definitions = match root.remove(definitions_keyword) {
+        Some(Value::Object(definitions)) => definitions,
+        _ => Map::new(),
+    };
+    hoist_discriminator_variants(schema, definitions_keyword, &mut definitions);
+    let names: Vec<String> = definitions.keys().cloned().collect();
+    names.iter().for_each(|name| {
+        // the name stays taken while its definition is searched
+        let mut definition = definitions[name].take();
+        hoist_discriminator_variants(&mut definition, definitions_keyword, &mut definitions);
+        definitions[name] = definition;
+    });
+    if !definitions.is_empty() {
+        schema[definitions_keyword] = Value::Object(definitions);
+    }
+}
+
+fn hoist_discriminator_variants(schema: &mut Value, definitions_keyword: &str, definitions: &mut Map<String, Value>) {
+    visit_schemas_mut(schema, &mut |schema| {
+        let Some(property) = schema["discriminator"]["propertyName"].as_str().map(str::to_string) else {
+            return;
+        };
+        let Some(Value::Array(variants)) = schema.get_mut("oneOf") else {
+            return;
+        };
+        let mut mapping = Map::new();
+        variants.iter_mut().for_each(|variant| {
+            let Some(tag) = variant["properties"][&property]["enum"][0].as_str().map(str::to_string) else {
+                return;
+            };
+            let name = unique_definition_name(definitions, &tag);
+            let reference = format!("#/{}/{}", definitions_keyword, escape_pointer_segment(&name));
+            let mut variant = std::mem::replace(variant, json!({"$ref": reference}));
+            definitions.insert(name.clone(), Value::Null);
+            // the variants can have discriminated unions of their own
+            hoist_discriminator_variants(&mut variant, definitions_keyword, definitions);
+            definitions.insert(name, variant);
+            mapping.insert(tag, reference.into());
+        });
+        if !mapping.is_empty() {
+            schema["discriminator"]["mapping"] = Value::Object(mapping);
+        }
+    });
+}
+
+// The minimum number of properties a nested object has to share with its ancestor to be
+// folded into it, unless they have exactly the same properties
+const MIN_SHARED_PROPERTIES: usize = 2;
+// Keywords that don't constrain the values a schema accepts
+const ANNOTATION_KEYWORDS: [&str; 10] = [
+    "$schema", "$defs", "definitions", "title", "description", "examples", "default", "$comment", "readOnly", "writeOnly",
+];
+
+/// Fold the object schemas that are nested in an instance of the same structure (e.g. the
+/// replies of a comment, which are comments themselves) into the schema of the outermost
+/// instance with `merge`, and replace them with a recursive "$ref" to it. The outermost
+/// instance is hoisted into the `definitions_keyword` of the root schema and named after its
+/// property, unless it's the root schema, which is referred to as "#".
+///
+/// A nested object is only an instance when it's found under the same property name as the
+/// instance it's nested in (e.g. "/a/a"), or has the property it's nested under itself (e.g. a
+/// comment in "replies" with "replies" of its own), and shares its properties. The instances
+/// are only folded if the merged schema accepts every one of them, otherwise they stay inline.
+pub fn fold_recursive_structures(schema: &mut Value, definitions_keyword: &str, merge: &impl Fn(&Value, &Value) -> Value) {
+    let mut definitions = Map::new();
+    fold_recursive_schema(schema, None, true, definitions_keyword, &mut definitions, merge);
+    if !definitions.is_empty() {
+        schema[definitions_keyword] = Value::Object(definitions);
+    }
+}
+
+fn fold_recursive_schema(
+    schema: &mut Value,
+    name: Option<&str>,
+    is_root: bool,
+    definitions_keyword: &str,
+    definitions: &mut Map<String, Value>,
+    merge: &impl Fn(&Value, &Value) -> Value,
+) {
+    if is_object_schema(schema) {
+        let definition_name = (!is_root).then(|| unique_definition_name(definitions, name.unwrap_or("definition")));
+        let reference = match &definition_name {
+            Some(definition_name) => format!("#/{}/{}", definitions_keyword, escape_pointer_segment(definition_name)),
+            None => "#".to_string(),
+        };
+        let reference_schema = json!({"$ref": reference});
+
+        // every instance has its nested instances replaced with the reference
+        let mut instance = schema.clone();
+        let mut nested = vec![];
+        take_nested_instances(&mut instance, schema, name, None, name, &reference_schema, &mut nested);
+        if !nested.is_empty() {
+            let mut instances = vec![instance];
+            while let Some((nested_name, mut nested_schema)) = nested.pop() {
+                let nested_instance = nested_schema.clone();
+                let nested_name = Some(nested_name.as_str());
+                take_nested_instances(&mut nested_schema, &nested_instance, nested_name, None, nested_name, &reference_schema, &mut nested);
+                instances.push(nested_schema);
+            }
+            let mut folded = instances[1..].iter().fold(instances[0].clone(), |folded, instance| merge(&folded, instance));
+            if instances.iter().all(|instance| accepts(&folded, instance, &reference_schema, &folded, 0)) {
+                for_each_subschema_mut(&mut folded, &mut |subschema_name, subschema| {
+                    fold_recursive_schema(subschema, subschema_name.or(name), false, definitions_keyword, definitions, merge);
+                });
+                match definition_name {
+                    Some(definition_name) => {
+                        definitions.insert(definition_name, folded);
+                        *schema = reference_schema;
+                    },
+                    None => *schema = folded,
+                }
+                return;
+            }
+        }
+    }
+    for_each_subschema_mut(schema, &mut |subschema_name, subschema| {
+        // unnamed subschemas (e.g. the "items" of an array) are named after their parent
+        fold_recursive_schema(subschema, subschema_name.or(name), false, definitions_keyword, definitions, merge);
+    });
+}
+
+/// Replace the outermost nested instances in the schema of an instance with the reference,
+/// and collect them along with the name they were found under. `instance_name` is the name
+/// the instance itself was found under, and `first_name` the name of the property of the
+/// instance the schema is nested in.
+fn take_nested_instances(
+    schema: &mut Value,
+    instance: &Value,
+    instance_name: Option<&str>,
+    first_name: Option<&str>,
+    name: Option<&str>,
+    reference: &Value,
+    taken: &mut Vec<(String, Value)>,
+) {
+    for_each_subschema_mut(schema, &mut |subschema_name, subschema| {
+        let name = subschema_name.or(name);
+        let first_name = first_name.or(subschema_name);
+        let is_nested_instance = name.is_some_and(|name| {
+            let recurs_by_name = instance_name == Some(name);
+            let has_nesting_property = first_name.is_some_and(|first_name| subschema["properties"].get(first_name).is_some());
+            (recurs_by_name || has_nesting_property) && shares_properties(subschema, instance)
+        });
+        if is_nested_instance {
+            taken.push((name.unwrap().to_string(), std::mem::replace(subschema, reference.clone())));
+        } else {
+            take_nested_instances(subschema, instance, instance_name, first_name, name, reference, taken);
+        }
+    });
+}
+
+fn is_object_schema(schema: &Value) -> bool {
+    match &schema["type"] {
+        Value::String(schema_type) => schema_type == "object",
+        Value::Array(schema_types) => schema_types.iter().any(|schema_type| schema_type == "object"),
+        _ => false,
+    }
+}
+
+/// Check if the object schema has enough properties in common with the instance, or exactly
+/// the same properties
+fn shares_properties(schema: &Value, instance: &Value) -> bool {
+    if !is_object_schema(schema) {
+        return false;
+    }
+    let (Some(Value::Object(properties)), Some(Value::Object(instance_properties))) =
+        (schema.get("properties"), instance.get("properties")) else {
+        return false;
+    };
+    let shared = properties.keys().filter(|key| instance_properties.contains_key(*key)).count();
+    let is_same_shape = shared == properties.len() && shared == instance_properties.len();
+    shared > 0 && (shared >= MIN_SHARED_PROPERTIES || is_same_shape)
+}
+
+/// Check if the schema accepts every value the inferred `subschema` describes, where the
+/// `reference` in either of them refers to `target`. The properties and types an inferred
+/// schema doesn't list were never seen, so they don't have to be accepted. The check is
+/// conservative, it fails for any keyword it can't compare.
+fn accepts(schema: &Value, subschema: &Value, reference: &Value, target: &Value, depth: usize) -> bool {
+    if schema == subschema {
+        return true;
+    }
+    // the references can be resolved indefinitely
+    if depth >= MAX_NESTING_DEPTH {
+        return false;
+    }
+    if subschema == reference {
+        return accepts(schema, target, reference, target, depth + 1);
+    }
+    if schema == reference {
+        return accepts(target, subschema, reference, target, depth + 1);
+    }
+    let (Value::Object(schema), Value::Object(subschema)) = (schema, subschema) else {
+        return false;
+    };
+    if let Some(Value::Array(branches)) = subschema.get("anyOf").or(subschema.get("oneOf")) {
+        return branches.iter().all(|branch| accepts(&Value::Object(schema.clone()), branch, reference, target, depth + 1));
+    }
+
+    let accepts_property = |property: &str, property_schema: &Value| -> bool {
+        match (schema.get("properties").and_then(|properties| properties.get(property)), schema.get("additionalProperties")) {
+            (Some(schema), _) => accepts(schema, property_schema, reference, target, depth + 1),
+            // the property could match one of the patterns with another schema
+            _ if schema.contains_key("patternProperties") => false,
+            (None, None) | (None, Some(Value::Bool(true))) => true,
+            (None, Some(Value::Bool(_))) => false,
+            (None, Some(additional)) => accepts(additional, property_schema, reference, target, depth + 1),
+        }
+    };
+    schema.iter()
+        .filter(|(keyword, _)| !ANNOTATION_KEYWORDS.contains(&keyword.as_str()) && !keyword.starts_with("x-"))
+        .all(|(keyword, value)| match keyword.as_str() {
+            "type" => {
+                let types = |schema_type: &Value| match schema_type {
+                    Value::Array(types) => types.clone(),
+                    schema_type => vec![schema_type.clone()],
+                };
+                let accepted_types = types(value);
+                subschema.get("type").is_some_and(|subschema_type| types(subschema_type).iter().all(|t| {
+                    // integers are numbers as well
+                    accepted_types.contains(t) || (t == "integer" && accepted_types.contains(&json!("number")))
+                }))
+            },
+            "anyOf" => value.as_array().unwrap().iter().any(|branch| {
+                accepts(branch, &Value::Object(subschema.clone()), reference, target, depth + 1)
+            }),
+            "required" => value.as_array().unwrap().iter().all(|property| {
+                subschema.get("required").and_then(Value::as_array).is_some_and(|required| required.contains(property))
+            }),
+            "properties" => match subschema.get("properties") {
+                Some(Value::Object(properties)) => properties.iter().all(|(property, property_schema)| accepts_property(property, property_schema)),
+                _ => true,
+            },
+            "additionalProperties" => match subschema.get("additionalProperties") {
+                None | Some(Value::Bool(false)) => {
+                    // the properties are checked against "additionalProperties" with the "properties"
+                    subschema.get("properties").and_then(Value::as_object).is_none_or(|properties| {
+                        properties.iter().all(|(property, property_schema)| accepts_property(property, property_schema))
+                    })
+                },
+                Some(additional) => value != &Value::Bool(false)
+                    && (value == &Value::Bool(true) || accepts(value, additional, reference, target, depth + 1)),
+            },
+            "items" if value.is_object() => match subschema.get("items") {
+                None => true,
+                Some(Value::Array(items)) => items.iter().all(|item| accepts(value, item, reference, target, depth + 1)),
+                Some(items) => accepts(value, items, reference, target, depth + 1),
+            },
+            _ => subschema.get(keyword) == Some(value),
+        })
+}
+
+/// Replace the local references of the schema, to the root ("#") or to its definitions (e.g.
+/// "#/$defs/address"), with the schema they refer to and remove the definitions, so that the
+/// schema can be merged like any other. A recursive reference is expanded into one more level
+/// of nesting, so that the recursion can be detected again, and then replaced with an empty schema.
+pub fn inline_local_references(schema: &mut Value) {
+    let Value::Object(root) = schema else {
+        return;
+    };
+    let mut targets = Map::new();
+    DEFINITIONS_KEYWORDS.iter().for_each(|keyword| {
+        if let Some(Value::Object(definitions)) = root.remove(*keyword) {
+            definitions.into_iter().for_each(|(name, definition)| {
+                targets.insert(format!("#/{}/{}", keyword, escape_pointer_segment(&name)), definition);
+            });
+        }
+    });
+    targets.insert("#".to_string(), schema.clone());
+    inline_references(schema, &targets, &mut vec!["#".to_string()], 0);
+}
+
+fn inline_references(schema: &mut Value, targets: &Map<String, Value>, expanding: &mut Vec<String>, depth: usize) {
+    // the nodes never go deeper than this, so neither do the references they'd need
+    if depth >= MAX_NESTING_DEPTH {
+        return;
+    }
+    let reference = schema.get("$ref").and_then(Value::as_str).map(str::to_string);
+    if let Some((reference, target)) = reference.and_then(|reference| targets.get_key_value(&reference)) {
+        let keywords = schema.as_object_mut().unwrap();
+        keywords.remove("$ref");
+        if expanding.iter().filter(|expanded| *expanded == reference).count() >= MAX_REFERENCE_EXPANSIONS {
+            return;
+        }
+        let mut target = target.clone();
+        if let Value::Object(target_keywords) = &mut target {
+            keywords.iter().for_each(|(key, value)| {
+                target_keywords.entry(key).or_insert_with(|| value.clone());
+            });
+        }
+        expanding.push(reference.clone());
+        inline_references(&mut target, targets, expanding, depth);
+        expanding.pop();
+        *schema = target;
+        return;
+    }
+    for_each_subschema_mut(schema, &mut |_, subschema| inline_references(subschema, targets, expanding, depth + 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_local_references_should_expand_recursion_one_level_deep() {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {"root": {"$ref": "#/$defs/node"}},
+            "$defs": {"node": {"type": "object", "properties": {"child": {"$ref": "#/$defs/node"}}}},
+        });
+        inline_local_references(&mut schema);
+        assert_eq!(schema, json!({
+            "type": "object",
+            "properties": {"root": {
+                "type": "object",
+                "properties": {"child": {"type": "object", "properties": {"child": {}}}},
+            }},
+        }));
+    }
+}
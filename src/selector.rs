@@ -0,0 +1,151 @@
+use simd_json::BorrowedValue;
+
+use crate::lexeme::Lexeme;
+
+/// Selector is a jq-style expression selecting the records to infer the schema from in each
+/// JSON document, e.g. ".data.items[]". It supports a subset of jq: the identity ".", fields
+/// (".name", or ."name" for names with special characters), array indices (".[0]") and
+/// iteration over the items of an array or the values of an object (".[]"), chained together.
+/// Unlike jq, missing fields and mismatched types select nothing instead of failing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Step {
+    Field(String),
+    Index(usize),
+    Iterate,
+}
+
+/// A step from a value to one of its children, on the way to a selected value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PathStep<'v> {
+    Field(&'v str),
+    Index(usize),
+}
+
+impl Selector {
+    /// Parse a selector expression, e.g. ".data.items[]"
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("Invalid selector {}: {}", expression, reason);
+        let mut steps = vec![];
+        let mut rest = expression.trim();
+        if !rest.starts_with('.') {
+            return Err(invalid("must start with \".\""));
+        }
+        while !rest.is_empty() {
+            if let Some(bracketed) = rest.strip_prefix(".[").or_else(|| rest.strip_prefix('[')) {
+                let (index, after) = bracketed.split_once(']').ok_or_else(|| invalid("unclosed \"[\""))?;
+                steps.push(match index.trim() {
+                    "" => Step::Iterate,
+                    index => Step::Index(index.parse().map_err(|_| invalid("array indices must be non-negative integers"))?),
+                });
+                rest = after;
+            } else if let Some(quoted) = rest.strip_prefix(".\"") {
+                let (field, after) = quoted.split_once('"').ok_or_else(|| invalid("unclosed quote"))?;
+                steps.push(Step::Field(field.to_string()));
+                rest = after;
+            } else if let Some(field) = rest.strip_prefix('.') {
+                let end = field.find(['.', '[']).unwrap_or(field.len());
+                let (field, after) = field.split_at(end);
+                if !field.is_empty() {
+                    if !field.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        return Err(invalid("field names with special characters must be quoted"));
+                    }
+                    steps.push(Step::Field(field.to_string()));
+                } else if !after.is_empty() && !after.starts_with('[') {
+                    return Err(invalid("expected a field name after \".\""));
+                }
+                rest = after;
+            } else {
+                return Err(invalid("expected \".\" or \"[\""));
+            }
+        }
+        Ok(Selector { steps })
+    }
+
+    /// Get the values the selector selects in the document, along with their path, which is
+    /// used to select the same values from the lexeme of the document
+    pub(crate) fn select<'v>(&'v self, document: &'v BorrowedValue<'v>) -> Vec<(&'v BorrowedValue<'v>, Vec<PathStep<'v>>)> {
+        let mut selected = vec![(document, vec![])];
+        for step in &self.steps {
+            selected = selected.into_iter().flat_map(|(value, path)| {
+                select_step(step, value).into_iter().map(move |(child, path_step)| {
+                    let mut path = path.clone();
+                    path.push(path_step);
+                    (child, path)
+                })
+            }).collect();
+        }
+        selected
+    }
+}
+
+fn select_step<'v>(step: &'v Step, value: &'v BorrowedValue<'v>) -> Vec<(&'v BorrowedValue<'v>, PathStep<'v>)> {
+    match (step, value) {
+        (Step::Field(field), BorrowedValue::Object(object)) => {
+            object.get(field.as_str())
+                .map(|child| vec![(child, PathStep::Field(field.as_str()))])
+                .unwrap_or_default()
+        },
+        (Step::Index(index), BorrowedValue::Array(array)) => {
+            array.get(*index).map(|child| vec![(child, PathStep::Index(*index))]).unwrap_or_default()
+        },
+        (Step::Iterate, BorrowedValue::Array(array)) => {
+            array.iter().enumerate().map(|(index, child)| (child, PathStep::Index(index))).collect()
+        },
+        (Step::Iterate, BorrowedValue::Object(object)) => {
+            object.iter().map(|(key, child)| (child, PathStep::Field(key.as_ref()))).collect()
+        },
+        _ => vec![],
+    }
+}
+
+/// Get the lexeme at the path in the lexeme of the document, if the path can be followed
+pub(crate) fn resolve_lexeme<'l>(document: &'l Lexeme, path: &[PathStep]) -> Option<&'l Lexeme> {
+    path.iter().try_fold(document, |lexeme, step| match (step, lexeme) {
+        // the parser keeps the last value of duplicate keys
+        (PathStep::Field(field), Lexeme::Object(entries)) => {
+            entries.iter().rev().find(|(key, _)| key == field).map(|(_, value)| value)
+        },
+        (PathStep::Index(index), Lexeme::Array(items)) => items.get(*index),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_selector() {
+        assert_eq!(Selector::parse(".").unwrap().steps, vec![]);
+        assert_eq!(Selector::parse(".data.items[]").unwrap().steps, vec![
+            Step::Field("data".to_string()), Step::Field("items".to_string()), Step::Iterate,
+        ]);
+        assert_eq!(Selector::parse(r#".[0]."the key".[]"#).unwrap().steps, vec![
+            Step::Index(0), Step::Field("the key".to_string()), Step::Iterate,
+        ]);
+        assert!(Selector::parse("data").is_err());
+        assert!(Selector::parse(".items[").is_err());
+        assert!(Selector::parse(".items[-1]").is_err());
+        assert!(Selector::parse(".the-key").is_err());
+    }
+
+    #[test]
+    fn test_select_paths() {
+        let mut json = br#"{"data": {"items": [{"a": 1}, {"a": 2}]}, "meta": 1}"#.to_vec();
+        let document = simd_json::to_borrowed_value(&mut json).unwrap();
+        let selector = Selector::parse(".data.items[]").unwrap();
+        let selected = selector.select(&document);
+        let paths: Vec<Vec<PathStep>> = selected.into_iter().map(|(_, path)| path).collect();
+        assert_eq!(paths, vec![
+            vec![PathStep::Field("data"), PathStep::Field("items"), PathStep::Index(0)],
+            vec![PathStep::Field("data"), PathStep::Field("items"), PathStep::Index(1)],
+        ]);
+        assert!(Selector::parse(".missing[]").unwrap().select(&document).is_empty());
+        assert!(Selector::parse(".meta[0]").unwrap().select(&document).is_empty());
+    }
+}
@@ -4,8 +4,9 @@ use simd_json;
 use rayon::prelude::*;
 use simd_json::prelude::TypedContainerValue;
 
-use crate::strategy::base::SchemaStrategy;
-use crate::node::{SchemaNode, DataType};
+use crate::lexeme::Lexeme;
+use crate::strategy::base::{SchemaStrategy, SizeRange};
+use crate::node::{SchemaNode, DataType, NodeContext, ITEMS_PATH_SEGMENT};
 
 // The number of objects overwhich parallel processing is more efficient
 // than serial processing. This is a heuristic value and may not always work
@@ -16,6 +17,7 @@ pub trait ListSchemaStrategy: SchemaStrategy {
     fn get_items_mut(&mut self) -> IterMut<SchemaNode>;
     fn get_items(&self) -> Iter<SchemaNode>;
     fn items_to_schema(&self) -> Value;
+    fn get_length(&self) -> Option<&SizeRange>;
 
     fn to_schema(&self) -> Value {
         let mut schema = SchemaStrategy::to_schema(self);
@@ -23,6 +25,9 @@ pub trait ListSchemaStrategy: SchemaStrategy {
         if self.get_items().len() > 0 {
             schema["items"] = self.items_to_schema();
         }
+        if let Some(length) = self.get_length() {
+            length.to_schema(&mut schema, "minItems", "maxItems");
+        }
         schema
     }
 
@@ -38,13 +43,29 @@ pub trait ListSchemaStrategy: SchemaStrategy {
 pub struct ListStrategy {
     extra_keywords: Value,
     items: [SchemaNode; 1],
+    // only tracked when size constraints are enabled
+    length: Option<SizeRange>,
+    items_context: NodeContext,
 }
 
 impl ListStrategy {
-    pub fn new() -> Self {
+    pub fn new(context: &NodeContext) -> Self {
+        let items_context = context.child(ITEMS_PATH_SEGMENT);
         ListStrategy {
             extra_keywords: json!({}),
-            items: [SchemaNode::new()],
+            items: [SchemaNode::new(items_context.clone())],
+            length: context.config.size_constraints.then(SizeRange::default),
+            items_context,
+        }
+    }
+
+    pub fn is_tuple_detection_enabled(&self) -> bool {
+        self.items_context.config.tuple_detection
+    }
+
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        if let Lexeme::Array(items) = lexeme {
+            items.iter().for_each(|item| self.items[0].add_lexeme(item));
         }
     }
 }
@@ -71,7 +92,12 @@ impl SchemaStrategy for ListStrategy {
         
         match object {
             simd_json::BorrowedValue::Array(objects) => {
-                let items = self.get_items_mut();
+                if let Some(length) = self.length.as_mut() {
+                    length.add_size(objects.len());
+                }
+                // the temporary nodes are merged into the items node, which counts the changes
+                let items_context = &self.items_context.untracked();
+                let items = self.items.iter_mut();
                 items.for_each(|node| {
                     // if the number of objects is less than 10, it is more efficient to
                     // add them to the schema node directly without incurring the overhead 
@@ -86,7 +112,7 @@ impl SchemaStrategy for ListStrategy {
                         // and processing each partition in parallel with their own schema node
                         // and then merging the results
                         let combined_node = objects.par_iter().fold(
-                            || SchemaNode::new(),
+                            || SchemaNode::new(items_context.clone()),
                             |mut temp_node, obj| {
                                 temp_node.add_object(DataType::Object(obj));
                                 temp_node
@@ -96,7 +122,7 @@ impl SchemaStrategy for ListStrategy {
                                 first_node.add_schema(DataType::SchemaNode(&next_node));
                                 first_node
                             } 
-                        ).unwrap_or(SchemaNode::new());
+                        ).unwrap_or_else(|| SchemaNode::new(items_context.clone()));
                         node.add_schema(DataType::SchemaNode(&combined_node));
                     }
                 });
@@ -106,8 +132,17 @@ impl SchemaStrategy for ListStrategy {
     }
 
     fn add_schema(&mut self, schema: &Value) {
+        self.add_extra_keywords(schema);
+        if let Some(length) = self.length.as_mut() {
+            length.add_schema(schema, "minItems", "maxItems");
+        }
         if let Value::Object(schema) = schema {
-            if schema.contains_key("items") {
+            if let Some(Value::Array(tuple_items)) = schema.get("items") {
+                // tuple-style schemas are only merged into lists when tuple detection is enabled
+                tuple_items.iter().for_each(|item| {
+                    self.items[0].add_schema(DataType::Schema(item));
+                });
+            } else if schema.contains_key("items") {
                 let items = self.get_items_mut();
                 items.for_each(|node| {
                     node.add_schema(DataType::Schema(&schema["items"]));
@@ -129,6 +164,10 @@ impl ListSchemaStrategy for ListStrategy {
     fn items_to_schema(&self) -> Value {
         self.items[0].to_schema()
     }
+
+    fn get_length(&self) -> Option<&SizeRange> {
+        self.length.as_ref()
+    }
 }
 
 /// strategy for tuple-style array schemas. Tuple-style arrays are arrays
@@ -138,21 +177,77 @@ impl ListSchemaStrategy for ListStrategy {
 pub struct TupleStrategy {
     extra_keywords: Value,
     items: Vec<SchemaNode>,
+    // only tracked when size constraints are enabled
+    length: Option<SizeRange>,
+    // the number of items of every tuple observed so far, only tracked when tuple detection is enabled
+    item_count: Option<usize>,
+    // set once the arrays are not tuple-like anymore, and the strategy should fall back to a list
+    is_list_like: bool,
+    context: NodeContext,
 }
 
 impl TupleStrategy {
-    pub fn new() -> Self {
+    pub fn new(context: &NodeContext) -> Self {
         TupleStrategy {
             extra_keywords: json!({}),
-            items: vec![SchemaNode::new()],
+            items: vec![SchemaNode::new(context.child("0"))],
+            length: context.config.size_constraints.then(SizeRange::default),
+            item_count: None,
+            is_list_like: false,
+            context: context.clone(),
+        }
+    }
+
+    /// Check if the array looks like a tuple, i.e. it has at least two items and they
+    /// are not all of the same type. Only used when tuple detection is enabled.
+    pub fn is_tuple_like(objects: &[simd_json::BorrowedValue]) -> bool {
+        objects.len() >= 2 && objects.iter().any(|obj| json_type(obj) != json_type(&objects[0]))
+    }
+
+    /// Check if the strategy should fall back to a list-style array, which is the case once
+    /// arrays of different lengths or items of different types at the same position have been
+    /// observed. Tuples only ever fall back to lists when tuple detection is enabled.
+    pub fn should_fall_back_to_list(&self) -> bool {
+        self.context.config.tuple_detection
+            && (self.is_list_like || self.items.iter().any(|node| node.has_multiple_types()))
+    }
+
+    pub fn is_tuple_detection_enabled(&self) -> bool {
+        self.context.config.tuple_detection
+    }
+
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        if let Lexeme::Array(items) = lexeme {
+            self.items.iter_mut().zip(items).for_each(|(node, item)| node.add_lexeme(item));
         }
     }
 
+    /// Convert into a list-style array strategy, merging the schemas of all the positions
+    pub fn into_list(self) -> ListStrategy {
+        let mut list = ListStrategy::new(&self.context);
+        list.extra_keywords = self.extra_keywords;
+        list.length = self.length;
+        self.items.iter().for_each(|node| {
+            list.items[0].add_schema(DataType::SchemaNode(node));
+        });
+        list
+    }
+
+    fn track_item_count(&mut self, item_count: usize) {
+        if !self.context.config.tuple_detection {
+            return;
+        }
+        if self.item_count.is_some_and(|count| count != item_count) {
+            self.is_list_like = true;
+        }
+        self.item_count = Some(item_count);
+    }
+
     fn add_items<Adder>(&mut self, items: Vec<DataType>, node_adder: Adder) 
     where Adder: Fn(&mut SchemaNode, DataType)
     {
         while self.items.len() < items.len() {
-            self.items.push(SchemaNode::new());
+            self.items.push(SchemaNode::new(self.context.child(&self.items.len().to_string())));
         }
         for (idx, item) in items.iter().enumerate() {
             node_adder(&mut self.items[idx], item.clone());
@@ -179,6 +274,10 @@ impl SchemaStrategy for TupleStrategy {
 
     fn add_object(&mut self, object: &simd_json::BorrowedValue) {
         if let simd_json::BorrowedValue::Array(objects) = object {
+            if let Some(length) = self.length.as_mut() {
+                length.add_size(objects.len());
+            }
+            self.track_item_count(objects.len());
             let items: Vec<DataType> = objects.iter().map(|obj| DataType::Object(obj)).collect();
             self.add_items(items, |node, obj| {
                 node.add_object(obj);
@@ -188,12 +287,20 @@ impl SchemaStrategy for TupleStrategy {
 
     fn add_schema(&mut self, schema: &Value) {
         self.add_extra_keywords(schema);
+        if let Some(length) = self.length.as_mut() {
+            length.add_schema(schema, "minItems", "maxItems");
+        }
         if schema.is_object() && schema["items"].is_array() {
             let items: Vec<DataType> = schema["items"].as_array().unwrap()
                 .iter().map(|s| DataType::Schema(s)).collect();
+            self.track_item_count(items.len());
             self.add_items(items, |node, sch| {
                 node.add_schema(sch);
             });
+        } else if schema["items"].is_object() && self.context.config.tuple_detection {
+            // a list-style schema, which is only matched when tuple detection is enabled
+            self.is_list_like = true;
+            self.items[0].add_schema(DataType::Schema(&schema["items"]));
         }
     }
 }
@@ -214,4 +321,19 @@ impl ListSchemaStrategy for TupleStrategy {
                 .collect()
         )
     }
-}
\ No newline at end of file
+
+    fn get_length(&self) -> Option<&SizeRange> {
+        self.length.as_ref()
+    }
+}
+/// Get the JSON type of the object, where integers and floats are the same "number" type
+fn json_type(object: &simd_json::BorrowedValue) -> &'static str {
+    match object {
+        simd_json::BorrowedValue::Static(simd_json::StaticNode::Null) => "null",
+        simd_json::BorrowedValue::Static(simd_json::StaticNode::Bool(_)) => "boolean",
+        simd_json::BorrowedValue::Static(_) => "number",
+        simd_json::BorrowedValue::String(_) => "string",
+        simd_json::BorrowedValue::Array(_) => "array",
+        simd_json::BorrowedValue::Object(_) => "object",
+    }
+}
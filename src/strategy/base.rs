@@ -52,3 +52,40 @@ pub trait ScalarSchemaStrategy: SchemaStrategy {
         schema
     }
 }
+
+/// Observed lower and upper bounds of a size, e.g. the length of a string,
+/// the number of items in an array or the number of properties in an object
+#[derive(Debug, Default, Clone)]
+pub struct SizeRange {
+    min: Option<usize>,
+    max: Option<usize>,
+}
+
+impl SizeRange {
+    pub fn add_size(&mut self, size: usize) {
+        self.min = Some(self.min.map_or(size, |min| min.min(size)));
+        self.max = Some(self.max.map_or(size, |max| max.max(size)));
+    }
+
+    /// Merge in the bounds from the `min_keyword` and `max_keyword` of the schema, a bound
+    /// that is missing from the schema leaves the corresponding observed bound untouched
+    pub fn add_schema(&mut self, schema: &Value, min_keyword: &str, max_keyword: &str) {
+        if let Some(min) = schema[min_keyword].as_u64() {
+            let min = min as usize;
+            self.min = Some(self.min.map_or(min, |m| m.min(min)));
+        }
+        if let Some(max) = schema[max_keyword].as_u64() {
+            let max = max as usize;
+            self.max = Some(self.max.map_or(max, |m| m.max(max)));
+        }
+    }
+
+    pub fn to_schema(&self, schema: &mut Value, min_keyword: &str, max_keyword: &str) {
+        if let Some(min) = self.min {
+            schema[min_keyword] = min.into();
+        }
+        if let Some(max) = self.max {
+            schema[max_keyword] = max.into();
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use serde_json::{json, Value};
+
+pub const DECIMAL_KEYWORD: &str = "x-genson-decimal";
+
+/// DecimalSize tracks the maximum number of digits before and after the decimal point of
+/// the observed numbers, as written in the input, so they can be stored as exact decimals,
+/// e.g. numbers like "1234.50" fit a DECIMAL(6,2) with a precision of 6 and a scale of 2
+#[derive(Debug, Default, Clone)]
+pub struct DecimalSize {
+    has_numbers: bool,
+    integer_digits: usize,
+    scale: usize,
+}
+
+impl DecimalSize {
+    /// Add the text of a JSON number, e.g. "-12.50" or "1.5e3"
+    pub fn add_lexeme(&mut self, lexeme: &str) {
+        let lexeme = lexeme.strip_prefix('-').unwrap_or(lexeme);
+        let (mantissa, exponent) = match lexeme.find(['e', 'E']) {
+            Some(idx) => (&lexeme[..idx], lexeme[idx + 1..].parse::<i64>().unwrap_or(0)),
+            None => (lexeme, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        // the number is the digits times 10 to the power of the shift
+        let digits = format!("{integer}{fraction}");
+        let significant_digits = digits.trim_start_matches('0').len() as i64;
+        let shift = exponent - fraction.len() as i64;
+
+        let scale = (-shift).max(0);
+        let integer_digits = (significant_digits + shift).max(0);
+        self.add_size(integer_digits as usize, scale as usize);
+    }
+
+    pub fn add_schema(&mut self, schema: &Value) {
+        let decimal = &schema[DECIMAL_KEYWORD];
+        if let (Some(precision), Some(scale)) = (decimal["precision"].as_u64(), decimal["scale"].as_u64()) {
+            self.add_size(precision.saturating_sub(scale) as usize, scale as usize);
+        }
+    }
+
+    pub fn to_schema(&self, schema: &mut Value) {
+        if self.has_numbers {
+            let precision = (self.integer_digits + self.scale).max(1);
+            schema[DECIMAL_KEYWORD] = json!({"precision": precision, "scale": self.scale});
+        }
+    }
+
+    fn add_size(&mut self, integer_digits: usize, scale: usize) {
+        self.has_numbers = true;
+        self.integer_digits = self.integer_digits.max(integer_digits);
+        self.scale = self.scale.max(scale);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal_of(lexeme: &str) -> Value {
+        let mut decimal = DecimalSize::default();
+        decimal.add_lexeme(lexeme);
+        let mut schema = json!({});
+        decimal.to_schema(&mut schema);
+        schema[DECIMAL_KEYWORD].clone()
+    }
+
+    #[test]
+    fn test_decimal_size_should_keep_trailing_zeros() {
+        assert_eq!(decimal_of("1234.50"), json!({"precision": 6, "scale": 2}));
+        assert_eq!(decimal_of("-0.05"), json!({"precision": 2, "scale": 2}));
+        assert_eq!(decimal_of("0"), json!({"precision": 1, "scale": 0}));
+    }
+
+    #[test]
+    fn test_decimal_size_should_apply_exponents() {
+        assert_eq!(decimal_of("1.5e3"), json!({"precision": 4, "scale": 0}));
+        assert_eq!(decimal_of("25E-4"), json!({"precision": 4, "scale": 4}));
+    }
+}
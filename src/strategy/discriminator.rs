@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+use simd_json::prelude::ValueAsScalar;
+
+use crate::config::{NullableStyle, SchemaConfig};
+use crate::lexeme::Lexeme;
+use crate::node::NodeContext;
+use crate::strategy::base::SchemaStrategy;
+use crate::strategy::object::ObjectStrategy;
+
+// The properties that are conventionally used to tag object variants, in the order of
+// precedence, when the discriminator property is auto-detected
+const DISCRIMINATOR_CANDIDATES: [&str; 7] = ["type", "kind", "@type", "_type", "event", "event_type", "eventType"];
+
+// Properties with more distinct values than this are not considered to be discriminators
+const MAX_VARIANTS: usize = 32;
+
+/// Keeps the value of a candidate property that has only had one value so far, which isn't
+/// emitted as a "const", so that the variants can still be told apart when merging builders
+pub const DISCRIMINATOR_VALUE_KEYWORD: &str = "x-genson-discriminator-value";
+
+/// DiscriminatedVariants infers one object schema per value of a discriminator property,
+/// e.g. the "type" tag of the events in an event stream. Every candidate property is tracked
+/// for as long as it's a string in every object, so the discriminator can be auto-detected.
+/// The candidates after the first one that discriminates are dropped, as they can't take
+/// precedence over it.
+#[derive(Debug)]
+pub struct DiscriminatedVariants {
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    property: String,
+    // the value of the property while all the objects have the same one, in which case the
+    // only variant is the schema of all the objects, and isn't inferred separately
+    single_tag: Option<String>,
+    // the object strategy of each variant, keyed by the discriminator value, once there
+    // is more than one
+    variants: BTreeMap<String, ObjectStrategy>,
+}
+
+impl Candidate {
+    /// Get the variant of the tag to add an object or schema to, which is None while the
+    /// tag is the only one. Once a second tag shows up, the first variant is made of the
+    /// merged schema of everything added so far.
+    fn variant_mut(&mut self, tag: &str, context: &NodeContext, merged_schema: &dyn Fn() -> Value) -> Option<&mut ObjectStrategy> {
+        if self.variants.is_empty() {
+            match self.single_tag.as_deref() {
+                None => {
+                    self.single_tag = Some(tag.to_string());
+                    return None;
+                },
+                Some(single_tag) if single_tag == tag => return None,
+                Some(_) => {
+                    let mut first_variant = ObjectStrategy::new_variant(context);
+                    first_variant.add_schema(&merged_schema());
+                    self.variants.insert(self.single_tag.take().unwrap(), first_variant);
+                },
+            }
+        }
+        Some(self.variants.entry(tag.to_string()).or_insert_with(|| ObjectStrategy::new_variant(context)))
+    }
+
+    fn is_discriminating(&self) -> bool {
+        self.variants.len() > 1
+    }
+}
+
+impl DiscriminatedVariants {
+    pub fn new(config: &SchemaConfig) -> Self {
+        DiscriminatedVariants {
+            candidates: DiscriminatedVariants::candidate_properties(config).into_iter()
+                .map(|property| Candidate { property, single_tag: None, variants: BTreeMap::new() })
+                .collect(),
+        }
+    }
+
+    /// Get the properties that can be the discriminator, which is either the one
+    /// named in the configuration, or any of the conventional ones
+    pub fn candidate_properties(config: &SchemaConfig) -> Vec<String> {
+        match &config.discriminator_property {
+            Some(property) => vec![property.clone()],
+            None => DISCRIMINATOR_CANDIDATES.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    /// Check if no property can be the discriminator anymore
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Add the object to its variant of each candidate, `merged_schema` is the schema of all
+    /// the objects added before it
+    pub fn add_object(&mut self, object: &simd_json::BorrowedValue, context: &NodeContext, merged_schema: &dyn Fn() -> Value) {
+        let simd_json::BorrowedValue::Object(properties) = object else {
+            return;
+        };
+        self.candidates.retain_mut(|candidate| {
+            let Some(tag) = properties.get(candidate.property.as_str()).and_then(|value| value.as_str()) else {
+                return false;
+            };
+            if let Some(variant) = candidate.variant_mut(tag, context, merged_schema) {
+                variant.add_object(object);
+            }
+            candidate.variants.len() <= MAX_VARIANTS
+        });
+        self.drop_overridden_candidates();
+    }
+
+    /// Drop the candidates after the first one that discriminates, which can't be the
+    /// discriminator unless it's dropped, so that objects aren't added to their variants
+    fn drop_overridden_candidates(&mut self) {
+        if let Some(idx) = self.candidates.iter().position(Candidate::is_discriminating) {
+            self.candidates.truncate(idx + 1);
+        }
+    }
+
+    /// Route the lexeme of an object to the variant of each candidate it was added to
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        let Lexeme::Object(entries) = lexeme else {
+            return;
+        };
+        self.candidates.iter_mut().for_each(|candidate| {
+            let tag = entries.iter().find_map(|(prop, value)| match value {
+                Lexeme::String(tag) if *prop == candidate.property => Some(tag),
+                _ => None,
+            });
+            if let Some(variant) = tag.and_then(|tag| candidate.variants.get_mut(tag)) {
+                variant.add_lexeme(lexeme);
+            }
+        });
+    }
+
+    /// Merge in a variant schema, which has a "const" (or single "enum" value) discriminator
+    /// property, or the schema of objects with a single value of the property
+    pub fn add_schema(&mut self, schema: &Value, context: &NodeContext, merged_schema: &dyn Fn() -> Value) {
+        self.candidates.retain_mut(|candidate| {
+            let property_schema = &schema["properties"][&candidate.property];
+            let tag = property_schema["const"].as_str()
+                .or(DiscriminatedVariants::single_enum_value(property_schema))
+                .or(property_schema[DISCRIMINATOR_VALUE_KEYWORD].as_str());
+            let Some(tag) = tag else {
+                return false;
+            };
+            if let Some(variant) = candidate.variant_mut(tag, context, merged_schema) {
+                variant.add_schema(schema);
+            }
+            candidate.variants.len() <= MAX_VARIANTS
+        });
+        self.drop_overridden_candidates();
+    }
+
+    /// Remove the "const" (or single "enum" value) of the candidate properties from a variant
+    /// schema, so that it can be merged into the schema of all variants
+    pub fn remove_discriminator_consts(schema: &Value, config: &SchemaConfig) -> Value {
+        let mut schema = schema.clone();
+        if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+            DiscriminatedVariants::candidate_properties(config).iter().for_each(|property| {
+                if let Some(property_schema) = properties.get_mut(property) {
+                    let is_single_enum = DiscriminatedVariants::single_enum_value(property_schema).is_some();
+                    let property_schema = property_schema.as_object_mut().unwrap();
+                    property_schema.remove("const");
+                    property_schema.remove(DISCRIMINATOR_VALUE_KEYWORD);
+                    if is_single_enum {
+                        property_schema.remove("enum");
+                    }
+                }
+            });
+        }
+        schema
+    }
+
+    /// Get the value of an "enum" with a single string value, which is how the OpenAPI style
+    /// tags the variants, as OpenAPI 3.0 has no "const"
+    fn single_enum_value(property_schema: &Value) -> Option<&str> {
+        match property_schema.get("enum") {
+            Some(Value::Array(values)) if values.len() == 1 => values[0].as_str(),
+            _ => None,
+        }
+    }
+
+    /// Generate the schema from the schema of all variants merged together. Objects with
+    /// more than one variant become a "oneOf" of the variant schemas, while objects with a
+    /// single variant keep the merged schema, as the property doesn't discriminate anything.
+    /// In the OpenAPI style, the "mapping" of the discriminator is only added once the variants
+    /// are moved to the definitions of the root schema, see `extract_discriminator_variants`.
+    pub fn to_schema(&self, mut merged_schema: Value, config: &SchemaConfig) -> Value {
+        let Some(candidate) = self.candidates.iter().find(|candidate| candidate.is_discriminating()) else {
+            self.candidates.iter().for_each(|candidate| {
+                if let Some(tag) = &candidate.single_tag {
+                    merged_schema["properties"][&candidate.property][DISCRIMINATOR_VALUE_KEYWORD] = tag.as_str().into();
+                }
+            });
+            return merged_schema;
+        };
+
+        let is_openapi = config.nullable_style == NullableStyle::OpenApi;
+        let variant_schemas: Vec<Value> = candidate.variants.iter().map(|(tag, variant)| {
+            let mut schema = variant.to_schema();
+            schema["properties"][&candidate.property] = if is_openapi {
+                json!({"type": "string", "enum": [tag]})
+            } else {
+                json!({"type": "string", "const": tag})
+            };
+            schema
+        }).collect();
+        let mut schema = json!({"oneOf": variant_schemas});
+        if is_openapi {
+            schema["discriminator"] = json!({"propertyName": candidate.property});
+        }
+        schema
+    }
+}
@@ -0,0 +1,126 @@
+use serde_json::Value;
+
+use crate::config::EncodedScalars;
+
+const INTEGER: u8 = 0b001;
+const NUMBER: u8 = 0b010;
+const BOOLEAN: u8 = 0b100;
+
+// The encoded types by the kinds of text they accept, in the order of precedence
+const ENCODED_TYPES: [(u8, &str); 3] = [(INTEGER, "integer"), (NUMBER, "number"), (BOOLEAN, "boolean")];
+
+pub const ENCODED_TYPE_KEYWORD: &str = "x-genson-encoded-type";
+/// Marks a coerced schema that also describes values natively of the encoded type, which is
+/// only known while merging builders, so that the strings are told apart from them again
+pub const NATIVE_VALUES_KEYWORD: &str = "x-genson-native-values";
+
+/// EncodedScalar tracks whether all the observed strings are the text of a number or a
+/// boolean, e.g. "42", "3.14" or "true", as sent by APIs that encode every value as a string
+#[derive(Debug, Clone)]
+pub struct EncodedScalar {
+    // the set of kinds every string observed so far matches
+    kinds: u8,
+}
+
+impl Default for EncodedScalar {
+    fn default() -> Self {
+        EncodedScalar { kinds: INTEGER | NUMBER | BOOLEAN }
+    }
+}
+
+impl EncodedScalar {
+    pub fn add_string(&mut self, s: &str) {
+        if self.kinds & INTEGER != 0 && !EncodedScalar::is_integer(s) {
+            self.kinds &= !INTEGER;
+        }
+        if self.kinds & NUMBER != 0 && !EncodedScalar::is_number(s) {
+            self.kinds &= !NUMBER;
+        }
+        if self.kinds & BOOLEAN != 0 && s != "true" && s != "false" {
+            self.kinds &= !BOOLEAN;
+        }
+    }
+
+    /// Merge in the encoded type annotation of the schema, a schema without
+    /// the annotation describes strings that aren't all encoded scalars
+    pub fn add_schema(&mut self, schema: &Value) {
+        let kind = ENCODED_TYPES.iter()
+            .find(|(_, encoded_type)| schema[ENCODED_TYPE_KEYWORD] == *encoded_type)
+            .map_or(0, |(kind, _)| *kind);
+        // integers are numbers as well
+        let kind = if kind == INTEGER { INTEGER | NUMBER } else { kind };
+        self.kinds &= kind;
+    }
+
+    /// Get the type all the observed strings are the text of, if any
+    pub fn encoded_type(&self) -> Option<&'static str> {
+        ENCODED_TYPES.iter()
+            .find(|(kind, _)| self.kinds & kind != 0)
+            .map(|(_, encoded_type)| *encoded_type)
+    }
+
+    /// Annotate the schema with the encoded type, and in coercion mode, replace
+    /// the type of the schema (and its examples) with the encoded type
+    pub fn to_schema(&self, schema: &mut Value, mode: EncodedScalars) {
+        let Some(encoded_type) = self.encoded_type() else {
+            schema.as_object_mut().unwrap().remove(ENCODED_TYPE_KEYWORD);
+            return;
+        };
+        schema[ENCODED_TYPE_KEYWORD] = encoded_type.into();
+        if mode == EncodedScalars::Coerce {
+            schema["type"] = encoded_type.into();
+            if let Some(Value::Array(examples)) = schema.get_mut("examples") {
+                examples.iter_mut().for_each(|example| {
+                    if let Some(value) = example.as_str().and_then(|s| serde_json::from_str(s).ok()) {
+                        *example = value;
+                    }
+                });
+            }
+        }
+    }
+
+    /// Check if the string is the text of an integer, without leading zeros as they
+    /// are significant in identifiers like zip codes
+    fn is_integer(s: &str) -> bool {
+        let digits = s.strip_prefix('-').unwrap_or(s);
+        !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit())
+            && (digits == "0" || !digits.starts_with('0'))
+    }
+
+    /// Check if the string is the text of a JSON number
+    fn is_number(s: &str) -> bool {
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(idx) => (&s[..idx], Some(&s[idx + 1..])),
+            None => (s, None),
+        };
+        let (integer, fraction) = match mantissa.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (mantissa, None),
+        };
+        let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let integer = integer.strip_prefix('-').unwrap_or(integer);
+        is_digits(integer)
+            && (integer == "0" || !integer.starts_with('0'))
+            && fraction.is_none_or(is_digits)
+            && exponent.is_none_or(|e| is_digits(e.strip_prefix(['+', '-']).unwrap_or(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_text_should_follow_json_grammar() {
+        ["0", "-1", "3.14", "1e10", "-2.5E-3"].iter().for_each(|s| assert!(EncodedScalar::is_number(s), "{s}"));
+        ["", "-", "01", "1.", ".5", "1e", "0x10", "NaN", " 1"].iter().for_each(|s| assert!(!EncodedScalar::is_number(s), "{s}"));
+    }
+
+    #[test]
+    fn test_integer_text_should_not_have_leading_zeros() {
+        assert!(EncodedScalar::is_integer("-42"));
+        assert!(!EncodedScalar::is_integer("007"));
+        assert!(!EncodedScalar::is_integer("1.0"));
+    }
+}
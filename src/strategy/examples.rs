@@ -0,0 +1,85 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde_json::Value;
+use simd_json::prelude::{ValueAsScalar, TypedScalarValue};
+
+use crate::node::NodeContext;
+
+/// A bounded sample of the distinct values observed for a schema node, emitted as
+/// "examples". The sample is a bottom-k reservoir: only the values with the k smallest
+/// hashes are kept. Unlike a classic reservoir sample, this makes the sample independent
+/// of the order the values were observed in, so samples built from different chunks of
+/// the data in parallel (and merged through their "examples") are identical to the
+/// sample that would have been built from the data in one go.
+#[derive(Debug, Clone)]
+pub struct ExampleSample {
+    capacity: usize,
+    // ordered by hash in ascending order
+    examples: Vec<(u64, Value)>,
+}
+
+impl ExampleSample {
+    pub fn new(capacity: usize) -> Self {
+        ExampleSample { capacity, examples: vec![] }
+    }
+
+    /// Sample the observed object, after it's masked by the configured redaction hook
+    pub fn add_object(&mut self, object: &simd_json::BorrowedValue, context: &NodeContext) {
+        let example = if object.is_str() {
+            Value::from(object.as_str().unwrap())
+        } else if object.is_bool() {
+            Value::from(object.as_bool().unwrap())
+        } else if let Some(number) = object.as_i64() {
+            Value::from(number)
+        } else if let Some(number) = object.as_u64() {
+            Value::from(number)
+        } else if let Some(number) = object.as_f64() {
+            Value::from(number)
+        } else {
+            return;
+        };
+
+        if let Some(redactor) = &context.config.example_redactor {
+            if let Some(example) = (redactor.0)(&context.path, example) {
+                self.add_example(example);
+            }
+        } else {
+            self.add_example(example);
+        }
+    }
+
+    /// Merge in the "examples" of the schema, which are assumed to be redacted already
+    pub fn add_schema(&mut self, schema: &Value) {
+        if let Value::Array(examples) = &schema["examples"] {
+            examples.iter().for_each(|example| self.add_example(example.clone()));
+        }
+    }
+
+    pub fn to_schema(&self, schema: &mut Value) {
+        if !self.examples.is_empty() {
+            schema["examples"] = self.examples.iter().map(|(_, example)| example.clone()).collect();
+        }
+    }
+
+    fn add_example(&mut self, example: Value) {
+        let hash = ExampleSample::hash(&example);
+        if self.examples.len() == self.capacity && self.examples.last().is_some_and(|(h, _)| hash >= *h) {
+            return;
+        }
+        if let Err(idx) = self.examples.binary_search_by_key(&hash, |(h, _)| *h) {
+            self.examples.insert(idx, (hash, example));
+            self.examples.truncate(self.capacity);
+        }
+    }
+
+    fn hash(example: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match example {
+            Value::String(s) => s.hash(&mut hasher),
+            // the textual form identifies numbers regardless of how they were parsed
+            other => other.to_string().hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+}
@@ -0,0 +1,96 @@
+use serde_json::Value;
+use simd_json::prelude::ValueAsScalar;
+
+// The formats of integers and other numbers by rank, where a higher rank fits more values.
+// Integers that don't fit any of the formats (e.g. u64 values larger than i64::MAX or values
+// beyond 64 bits) get no format.
+const INTEGER_FORMATS: [&str; 2] = ["int32", "int64"];
+const FLOAT_FORMATS: [&str; 2] = ["float", "double"];
+/// Keeps the float format of integer schemas, which is only known while merging builders,
+/// in case the integers are merged with other numbers
+pub const FLOAT_FORMAT_KEYWORD: &str = "x-genson-float-format";
+
+/// NumberFormat tracks the smallest OpenAPI "format" that fits all the observed numbers,
+/// both for when they are all integers, and for when they are not.
+#[derive(Debug, Default, Clone)]
+pub struct NumberFormat {
+    integer_rank: usize,
+    float_rank: usize,
+}
+
+impl NumberFormat {
+    /// Add an observed number, where `is_integer` tells if it's inferred as an integer
+    pub fn add_object(&mut self, object: &simd_json::BorrowedValue, is_integer: bool) {
+        if let Some(number) = object.as_i64() {
+            self.add_integer(number as i128);
+        } else if let Some(number) = object.as_u64() {
+            self.add_integer(number as i128);
+        } else if let Some(number) = object.as_f64() {
+            if is_integer {
+                self.add_integer_float(number);
+            }
+            // a "float" loses the precision of numbers that aren't exactly representable
+            if (number as f32) as f64 != number {
+                self.float_rank = self.float_rank.max(1);
+            }
+        }
+    }
+
+    /// Merge in the "format" of the schema, a schema without a known format doesn't
+    /// fit any format smaller than the largest one
+    pub fn add_schema(&mut self, schema: &Value) {
+        let format = schema["format"].as_str().unwrap_or_default();
+        if schema["type"] == "integer" {
+            let rank = INTEGER_FORMATS.iter().position(|f| *f == format).unwrap_or(INTEGER_FORMATS.len());
+            self.integer_rank = self.integer_rank.max(rank);
+            let float_format = schema[FLOAT_FORMAT_KEYWORD].as_str().unwrap_or_default();
+            let rank = FLOAT_FORMATS.iter().position(|f| *f == float_format).unwrap_or_default();
+            self.float_rank = self.float_rank.max(rank);
+        } else {
+            let rank = FLOAT_FORMATS.iter().position(|f| *f == format).unwrap_or(FLOAT_FORMATS.len() - 1);
+            self.float_rank = self.float_rank.max(rank);
+        }
+    }
+
+    pub fn to_schema(&self, schema: &mut Value, number_type: &str) {
+        let format = if number_type == "integer" {
+            INTEGER_FORMATS.get(self.integer_rank)
+        } else {
+            FLOAT_FORMATS.get(self.float_rank)
+        };
+        if let Some(format) = format {
+            schema["format"] = (*format).into();
+        } else {
+            schema.as_object_mut().unwrap().remove("format");
+        }
+        if number_type == "integer" && self.float_rank > 0 {
+            schema[FLOAT_FORMAT_KEYWORD] = FLOAT_FORMATS[self.float_rank].into();
+        } else {
+            schema.as_object_mut().unwrap().remove(FLOAT_FORMAT_KEYWORD);
+        }
+    }
+
+    fn add_integer(&mut self, number: i128) {
+        // integers with more significant bits than a float has only fit a "double"
+        if (number as f32) as i128 != number {
+            self.float_rank = self.float_rank.max(1);
+        }
+        let rank = if i32::try_from(number).is_ok() {
+            0
+        } else if i64::try_from(number).is_ok() {
+            1
+        } else {
+            INTEGER_FORMATS.len()
+        };
+        self.integer_rank = self.integer_rank.max(rank);
+    }
+
+    fn add_integer_float(&mut self, number: f64) {
+        // i64::MAX isn't representable as a float, and rounds up to 2^63
+        if number >= i64::MIN as f64 && number < i64::MAX as f64 {
+            self.add_integer(number as i128);
+        } else {
+            self.integer_rank = INTEGER_FORMATS.len();
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use serde_json::Value;
+
+use crate::config::SchemaConfig;
+use crate::strategy::pattern::StringPattern;
+
+const INTEGER: u8 = 0b001;
+const UUID: u8 = 0b010;
+const DATE: u8 = 0b100;
+
+// The patterns describing the key shapes, in the order of precedence
+const KEY_SHAPE_PATTERNS: [(u8, &str); 3] = [
+    (INTEGER, r"^\d+$"),
+    (UUID, "^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$"),
+    (DATE, r"^\d{4}-\d{2}-\d{2}$"),
+];
+
+/// MapKeys tracks the keys of an object to detect whether it's a map (a.k.a. dictionary)
+/// with dynamic keys, e.g. an object keyed by user IDs, dates or SKUs, rather than a
+/// record with a fixed set of properties.
+#[derive(Debug, Clone)]
+pub struct MapKeys {
+    // the set of shapes every key observed so far matches
+    shapes: u8,
+    has_keys: bool,
+    // only tracked when property names are enabled
+    pattern: Option<StringPattern>,
+}
+
+impl MapKeys {
+    pub fn new(config: &SchemaConfig) -> Self {
+        MapKeys {
+            shapes: INTEGER | UUID | DATE,
+            has_keys: false,
+            pattern: config.map_property_names.then(StringPattern::default),
+        }
+    }
+
+    pub fn add_key(&mut self, key: &str) {
+        self.has_keys = true;
+        if self.shapes & INTEGER != 0 && !MapKeys::is_integer(key) {
+            self.shapes &= !INTEGER;
+        }
+        if self.shapes & UUID != 0 && !MapKeys::is_uuid(key) {
+            self.shapes &= !UUID;
+        }
+        if self.shapes & DATE != 0 && !MapKeys::is_date(key) {
+            self.shapes &= !DATE;
+        }
+        if let Some(pattern) = self.pattern.as_mut() {
+            pattern.add_string(key);
+        }
+    }
+
+    /// Merge in the "propertyNames" schema of a map schema
+    pub fn add_schema(&mut self, property_names: &Value) {
+        self.has_keys = true;
+        let shape = KEY_SHAPE_PATTERNS.iter()
+            .find(|(_, pattern)| property_names["pattern"] == *pattern)
+            .map_or(0, |(shape, _)| *shape);
+        self.shapes &= shape;
+        if let Some(pattern) = self.pattern.as_mut() {
+            pattern.add_schema(property_names);
+        }
+    }
+
+    /// Check if an object with the given number of distinct keys should be treated as a map
+    pub fn is_map(&self, distinct_keys: usize, config: &SchemaConfig) -> bool {
+        let has_key_shape = config.map_key_shapes && self.has_keys && self.shapes != 0;
+        let is_diverse = config.map_key_threshold.is_some_and(|threshold| distinct_keys > threshold);
+        has_key_shape || is_diverse
+    }
+
+    pub fn to_schema(&self, schema: &mut Value) {
+        let Some(pattern) = &self.pattern else {
+            return;
+        };
+        let mut property_names = serde_json::json!({});
+        let shape = KEY_SHAPE_PATTERNS.iter().find(|(shape, _)| self.has_keys && self.shapes & shape != 0);
+        if let Some((_, shape_pattern)) = shape {
+            property_names["pattern"] = (*shape_pattern).into();
+        } else {
+            pattern.to_schema(&mut property_names);
+        }
+        if property_names.get("pattern").is_some() {
+            schema["propertyNames"] = property_names;
+        } else {
+            schema.as_object_mut().unwrap().remove("propertyNames");
+        }
+    }
+
+    fn is_integer(key: &str) -> bool {
+        !key.is_empty() && key.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    fn is_uuid(key: &str) -> bool {
+        key.len() == 36 && key.bytes().enumerate().all(|(idx, b)| match idx {
+            8 | 13 | 18 | 23 => b == b'-',
+            _ => b.is_ascii_hexdigit(),
+        })
+    }
+
+    fn is_date(key: &str) -> bool {
+        key.len() == 10 && key.bytes().enumerate().all(|(idx, b)| match idx {
+            4 | 7 => b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+    }
+}
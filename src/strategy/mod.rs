@@ -2,9 +2,21 @@ pub mod base;
 pub mod scalar;
 pub mod array;
 pub mod object;
+pub mod pattern;
+pub mod examples;
+pub mod map;
+pub mod discriminator;
+pub mod stats;
+pub mod format;
+pub mod encoded;
+pub mod timestamp;
+pub mod decimal;
 
 use serde_json::Value;
 
+use crate::lexeme::Lexeme;
+use crate::node::NodeContext;
+
 use array::{ListStrategy, TupleStrategy};
 use object::ObjectStrategy;
 use scalar::{BooleanStrategy, NullStrategy, NumberStrategy, StringStrategy, TypelessStrategy};
@@ -14,7 +26,7 @@ use self::array::ListSchemaStrategy;
 
 #[derive(Debug)]
 pub enum BasicSchemaStrategy {
-    Object(ObjectStrategy),
+    Object(Box<ObjectStrategy>),
     List(ListStrategy),
     Tuple(TupleStrategy),
     Null(NullStrategy),
@@ -28,41 +40,43 @@ pub enum BasicSchemaStrategy {
 
 impl BasicSchemaStrategy {
 
-    pub fn new_for_object(object: &simd_json::BorrowedValue) -> Option<Self> {
+    pub fn new_for_object(object: &simd_json::BorrowedValue, context: &NodeContext) -> Option<Self> {
         if ObjectStrategy::match_object(object) {
-            Some(BasicSchemaStrategy::Object(ObjectStrategy::new()))
+            Some(BasicSchemaStrategy::Object(Box::new(ObjectStrategy::new(context))))
+        } else if context.config.tuple_detection && BasicSchemaStrategy::is_tuple_like(object) {
+            Some(BasicSchemaStrategy::Tuple(TupleStrategy::new(context)))
         } else if <ListStrategy as ListSchemaStrategy>::match_object(object) {
-            Some(BasicSchemaStrategy::List(ListStrategy::new()))
+            Some(BasicSchemaStrategy::List(ListStrategy::new(context)))
         } else if <TupleStrategy as ListSchemaStrategy>::match_object(object) {
-            Some(BasicSchemaStrategy::Tuple(TupleStrategy::new()))
+            Some(BasicSchemaStrategy::Tuple(TupleStrategy::new(context)))
         } else if <NullStrategy as SchemaStrategy>::match_object(object) {
             Some(BasicSchemaStrategy::Null(NullStrategy::new()))
         } else if <BooleanStrategy as SchemaStrategy>::match_object(object) {
-            Some(BasicSchemaStrategy::Boolean(BooleanStrategy::new()))
+            Some(BasicSchemaStrategy::Boolean(BooleanStrategy::new(context)))
         } else if <NumberStrategy as SchemaStrategy>::match_object(object) {
-            Some(BasicSchemaStrategy::Number(NumberStrategy::new()))
+            Some(BasicSchemaStrategy::Number(NumberStrategy::new(context)))
         } else if <StringStrategy as SchemaStrategy>::match_object(object) {
-            Some(BasicSchemaStrategy::String(StringStrategy::new()))
+            Some(BasicSchemaStrategy::String(StringStrategy::new(context)))
         } else {
             None
         }
     }
 
-    pub fn new_for_schema(schema: &Value) -> Option<Self> {
+    pub fn new_for_schema(schema: &Value, context: &NodeContext) -> Option<Self> {
         if ObjectStrategy::match_schema(schema) {
-            Some(BasicSchemaStrategy::Object(ObjectStrategy::new()))
+            Some(BasicSchemaStrategy::Object(Box::new(ObjectStrategy::new(context))))
         } else if ListStrategy::match_schema(schema) {
-            Some(BasicSchemaStrategy::List(ListStrategy::new()))
+            Some(BasicSchemaStrategy::List(ListStrategy::new(context)))
         } else if TupleStrategy::match_schema(schema) {
-            Some(BasicSchemaStrategy::Tuple(TupleStrategy::new()))
+            Some(BasicSchemaStrategy::Tuple(TupleStrategy::new(context)))
         } else if <NullStrategy as SchemaStrategy>::match_schema(schema) {
             Some(BasicSchemaStrategy::Null(NullStrategy::new()))
         } else if <BooleanStrategy as SchemaStrategy>::match_schema(schema) {
-            Some(BasicSchemaStrategy::Boolean(BooleanStrategy::new()))
+            Some(BasicSchemaStrategy::Boolean(BooleanStrategy::new(context)))
         } else if <NumberStrategy as SchemaStrategy>::match_schema(schema) {
-            Some(BasicSchemaStrategy::Number(NumberStrategy::new()))
+            Some(BasicSchemaStrategy::Number(NumberStrategy::new(context)))
         } else if <StringStrategy as SchemaStrategy>::match_schema(schema) {
-            Some(BasicSchemaStrategy::String(StringStrategy::new()))
+            Some(BasicSchemaStrategy::String(StringStrategy::new(context)))
         } else {
             None
         }
@@ -97,8 +111,12 @@ impl BasicSchemaStrategy {
     pub fn match_schema(&self, schema: &Value) -> bool {
         match self {
             BasicSchemaStrategy::Object(_) => ObjectStrategy::match_schema(schema),
-            BasicSchemaStrategy::List(_) => ListStrategy::match_schema(schema),
-            BasicSchemaStrategy::Tuple(_) => TupleStrategy::match_schema(schema),
+            // when tuple detection is enabled, list and tuple strategies fall back to lists
+            // as needed, so both of them match any array schema
+            BasicSchemaStrategy::List(strategy) => ListStrategy::match_schema(schema)
+                || (strategy.is_tuple_detection_enabled() && TupleStrategy::match_schema(schema)),
+            BasicSchemaStrategy::Tuple(strategy) => TupleStrategy::match_schema(schema)
+                || (strategy.is_tuple_detection_enabled() && ListStrategy::match_schema(schema)),
             BasicSchemaStrategy::Null(_) => <NullStrategy as SchemaStrategy>::match_schema(schema),
             BasicSchemaStrategy::Boolean(_) => <BooleanStrategy as SchemaStrategy>::match_schema(schema),
             BasicSchemaStrategy::Number(_) => <NumberStrategy as SchemaStrategy>::match_schema(schema),
@@ -118,6 +136,7 @@ impl BasicSchemaStrategy {
             BasicSchemaStrategy::String(strategy) => strategy.add_schema(schema),
             BasicSchemaStrategy::Typeless(strategy) => strategy.add_schema(schema),
         }
+        self.fall_back_to_list_if_needed();
     }
 
     pub fn add_object(&mut self, object: &simd_json::BorrowedValue) {
@@ -131,5 +150,36 @@ impl BasicSchemaStrategy {
             BasicSchemaStrategy::String(strategy) => strategy.add_object(object),
             BasicSchemaStrategy::Typeless(strategy) => strategy.add_object(object),
         }
+        self.fall_back_to_list_if_needed();
+    }
+
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        match self {
+            BasicSchemaStrategy::Object(strategy) => strategy.add_lexeme(lexeme),
+            BasicSchemaStrategy::List(strategy) => strategy.add_lexeme(lexeme),
+            BasicSchemaStrategy::Tuple(strategy) => strategy.add_lexeme(lexeme),
+            BasicSchemaStrategy::Number(strategy) => strategy.add_lexeme(lexeme),
+            _ => (),
+        }
+    }
+
+    fn is_tuple_like(object: &simd_json::BorrowedValue) -> bool {
+        if let simd_json::BorrowedValue::Array(objects) = object {
+            TupleStrategy::is_tuple_like(objects)
+        } else {
+            false
+        }
+    }
+
+    /// Replace a tuple strategy with a list strategy once the arrays stop looking like tuples
+    fn fall_back_to_list_if_needed(&mut self) {
+        if let BasicSchemaStrategy::Tuple(strategy) = self {
+            if strategy.should_fall_back_to_list() {
+                let placeholder = BasicSchemaStrategy::Typeless(TypelessStrategy::new());
+                if let BasicSchemaStrategy::Tuple(strategy) = std::mem::replace(self, placeholder) {
+                    *self = BasicSchemaStrategy::List(strategy.into_list());
+                }
+            }
+        }
     }
 }
\ No newline at end of file
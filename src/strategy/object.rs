@@ -6,29 +6,159 @@ use serde_json::{Value, json, Map};
 use simd_json;
 use simd_json::prelude::TypedContainerValue;
 
-use crate::node::{SchemaNode, DataType};
-use crate::strategy::base::SchemaStrategy;
+use crate::config::SchemaConfig;
+use crate::lexeme::Lexeme;
+use crate::node::{SchemaNode, DataType, NodeContext, ITEMS_PATH_SEGMENT};
+use crate::path::matches_any_path;
+use crate::strategy::base::{SchemaStrategy, SizeRange};
+use crate::strategy::discriminator::DiscriminatedVariants;
+use crate::strategy::map::MapKeys;
+
+// Keywords that describe the properties of an object, which aren't kept at the maximum depth
+const PROPERTY_KEYWORDS: [&str; 5] = ["properties", "patternProperties", "additionalProperties", "required", "propertyNames"];
 
 #[derive(Debug)]
 pub struct ObjectStrategy {
     // TODO: this is redeclared everywhere, how to avoid this?
     extra_keywords: Value,
     properties: HashMap<String, SchemaNode>,
-    pattern_properties: HashMap<String, SchemaNode>,
+    pattern_properties: Vec<PatternProperty>,
     required_properties: Option<HashSet<String>>,
     include_empty_required: bool,
+    // only tracked when size constraints are enabled
+    property_count: Option<SizeRange>,
+    // only tracked when map detection is enabled
+    map_keys: Option<MapKeys>,
+    // the merged schema of all property values, once the object is detected to be a map
+    map_values: Option<SchemaNode>,
+    // only tracked when discriminated unions are enabled, and dropped once no property
+    // can be the discriminator
+    variants: Option<DiscriminatedVariants>,
+    // only tracked when a required threshold is configured
+    property_counts: Option<PropertyCounts>,
+    // the names of the properties left out by the path filters, so they're only matched once
+    excluded_properties: HashSet<String>,
+    context: NodeContext,
 }
 
 impl ObjectStrategy {
-    pub fn new() -> Self {
+    pub fn new(context: &NodeContext) -> Self {
+        // the properties of an object at the maximum depth aren't tracked
+        let tracks_properties = !context.is_at_max_depth();
         ObjectStrategy {
             extra_keywords: json!({}),
             properties: HashMap::new(),
-            pattern_properties: HashMap::new(),
+            pattern_properties: vec![],
             required_properties: None,
             include_empty_required: false,
+            property_count: context.config.size_constraints.then(SizeRange::default),
+            map_keys: (tracks_properties && is_map_detection_enabled(&context.config)).then(|| MapKeys::new(&context.config)),
+            map_values: None,
+            variants: (tracks_properties && is_discriminator_detection_enabled(&context.config))
+                .then(|| DiscriminatedVariants::new(&context.config)),
+            property_counts: context.config.required_threshold.filter(|_| tracks_properties).map(|_| PropertyCounts::default()),
+            excluded_properties: HashSet::new(),
+            context: context.clone(),
+        }
+    }
+
+    /// Get the node of the property, which is created the first time the property is seen,
+    /// unless the path filters leave it out. The excluded properties are remembered, so that
+    /// they're only matched once.
+    fn property_node(&mut self, prop: &str) -> Option<&mut SchemaNode> {
+        if !self.properties.contains_key(prop) {
+            if self.excluded_properties.contains(prop) {
+                return None;
+            }
+            let context = self.context.child(prop);
+            if context.excluded {
+                self.excluded_properties.insert(prop.to_string());
+                return None;
+            }
+            self.properties.insert(prop.to_string(), SchemaNode::new(context));
+        }
+        self.properties.get_mut(prop)
+    }
+
+    /// Create the strategy for a single variant of a discriminated union, which
+    /// doesn't detect variants itself
+    pub fn new_variant(context: &NodeContext) -> Self {
+        let mut strategy = ObjectStrategy::new(context);
+        strategy.variants = None;
+        strategy
+    }
+}
+
+/// PropertyCounts tracks how many objects were observed and how many of them had each
+/// property. The counts are kept in the schema of the nodes as internal keywords, so they
+/// can be merged from other builders, regardless of how the objects were split between them.
+/// They are left out of the schema the builder exports.
+#[derive(Debug, Default)]
+struct PropertyCounts {
+    objects: u64,
+    properties: HashMap<String, u64>,
+}
+
+pub const OBJECT_COUNT_KEYWORD: &str = "x-genson-object-count";
+pub const PROPERTY_COUNTS_KEYWORD: &str = "x-genson-property-counts";
+
+impl PropertyCounts {
+    fn add_object(&mut self, properties: &HashSet<String>) {
+        self.objects += 1;
+        properties.iter().for_each(|prop| {
+            *self.properties.entry(prop.to_string()).or_insert(0) += 1;
+        });
+    }
+
+    fn add_schema(&mut self, schema_object: &Map<String, Value>) {
+        if let Some(objects) = schema_object.get(OBJECT_COUNT_KEYWORD).and_then(Value::as_u64) {
+            self.objects += objects;
+        }
+        if let Some(Value::Object(properties)) = schema_object.get(PROPERTY_COUNTS_KEYWORD) {
+            properties.iter().for_each(|(prop, count)| {
+                *self.properties.entry(prop.to_string()).or_insert(0) += count.as_u64().unwrap_or(0);
+            });
+        }
+    }
+
+    fn to_schema(&self, schema: &mut Value) {
+        schema[OBJECT_COUNT_KEYWORD] = self.objects.into();
+        schema[PROPERTY_COUNTS_KEYWORD] = self.properties.iter()
+            .map(|(prop, count)| (prop.to_string(), Value::from(*count)))
+            .collect::<Map<String, Value>>()
+            .into();
+    }
+}
+
+/// PatternProperty holds the schema node for the values of the properties whose
+/// names match the pattern, along with the compiled regex of the pattern
+#[derive(Debug)]
+struct PatternProperty {
+    pattern: String,
+    // None if the pattern is not supported by the regex engine, in which
+    // case no property name is matched against it
+    regex: Option<Regex>,
+    node: SchemaNode,
+}
+
+impl PatternProperty {
+    fn new(pattern: &str, context: &NodeContext) -> Self {
+        // reuse the regexes compiled for the configuration where possible
+        let regex = context.config.pattern_properties.iter()
+            .find(|regex| regex.as_str() == pattern)
+            .cloned()
+            .or_else(|| Regex::new(pattern).ok());
+        PatternProperty {
+            pattern: pattern.to_string(),
+            regex,
+            // the values are addressed with "*", as the values of maps are
+            node: SchemaNode::new(context.child(ITEMS_PATH_SEGMENT)),
         }
     }
+
+    fn is_match(&self, prop: &str) -> bool {
+        self.regex.as_ref().is_some_and(|regex| regex.is_match(prop))
+    }
 }
 
 impl SchemaStrategy for ObjectStrategy {
@@ -49,94 +179,152 @@ impl SchemaStrategy for ObjectStrategy {
     }
 
     fn add_object(&mut self, object: &simd_json::BorrowedValue) {
+        if let Some(mut variants) = self.variants.take() {
+            // the objects so far make up the first variant, once a second one shows up
+            variants.add_object(object, &self.context, &|| self.to_merged_schema());
+            if !variants.is_empty() {
+                self.variants = Some(variants);
+            }
+        }
+
         let mut properties = HashSet::new();
         if let simd_json::BorrowedValue::Object(object) = object {
+            if let Some(property_count) = self.property_count.as_mut() {
+                property_count.add_size(object.len());
+            }
+            if self.context.is_at_max_depth() {
+                return;
+            }
             object.iter().for_each(|(prop, subobj)| {
-                let mut pattern: Option<&str> = None;
+                // the values of pattern properties and maps are matched by the path filters as "*"
                 if !self.properties.contains_key(prop.as_ref()) {
-                    let pattern_matcher = |p: &str| Regex::new(p).unwrap().is_match(prop);
-                    self.pattern_properties
-                        .iter_mut()
-                        .find(|(p, _)| pattern_matcher(p))
-                        .map(|(p, node)| {
-                            pattern = Some(p);
-                            node.add_object(DataType::Object(subobj));
-                        });
-                }
-
-                if pattern.is_none() {
-                    properties.insert(prop.to_string());
-                    if !self.properties.contains_key(prop.as_ref()) {
-                        self.properties.insert(prop.to_string(), SchemaNode::new());
+                    if let Some(node) = self.find_pattern_property(prop) {
+                        node.add_object(DataType::Object(subobj));
+                        return;
                     }
-                    self.properties.get_mut(prop.as_ref()).unwrap().add_object(DataType::Object(subobj));
+                    if let Some(map_values) = self.map_values.as_mut() {
+                        if let Some(map_keys) = self.map_keys.as_mut() {
+                            map_keys.add_key(prop);
+                        }
+                        map_values.add_object(DataType::Object(subobj));
+                        return;
+                    }
+                }
+                let Some(node) = self.property_node(prop) else {
+                    return;
+                };
+                node.add_object(DataType::Object(subobj));
+                properties.insert(prop.to_string());
+                if let Some(map_keys) = self.map_keys.as_mut() {
+                    map_keys.add_key(prop);
                 }
             });
         }
 
+        if self.map_values.is_some() {
+            return;
+        }
+        if let Some(property_counts) = self.property_counts.as_mut() {
+            property_counts.add_object(&properties);
+        }
         if self.required_properties.is_none() {
             self.required_properties = Some(properties);
         } else {
             // take the intersection
-            self.required_properties.as_mut().unwrap().retain(|p| properties.contains(p));
+            let required_properties = self.required_properties.as_mut().unwrap();
+            let required_count = required_properties.len();
+            required_properties.retain(|p| properties.contains(p));
+            if required_properties.len() < required_count {
+                self.context.mark_structure_changed();
+            }
         }
+        self.detect_map();
     }
 
     fn add_schema(&mut self, schema: &Value) {
-        if let Value::Object(schema_object) = schema {
-            self.add_extra_keywords(schema);
-
-            // properties updater updates the internal properties and pattern_properties with the schema_object,
-            // creating schema node as needed for each property
-            let properties_updater = 
-                    |properties: &mut HashMap<String, SchemaNode>, schema_object: &Map<String, Value>, prop_key: &str| {
-                if let Some(schema_properties) = schema_object[prop_key].as_object() {
-                    schema_properties.iter().for_each(|(prop, sub_schema)| {
-                        let sub_node = properties.entry(prop.to_string())
-                            .or_insert(SchemaNode::new());
-                        sub_node.add_schema(DataType::Schema(sub_schema));
-                    });
-                }
-            };
-
-            if schema_object.contains_key("properties") {
-                properties_updater(&mut self.properties, schema_object, "properties");
+        if self.context.is_at_max_depth() {
+            let mut schema = schema.clone();
+            if let Value::Object(keywords) = &mut schema {
+                PROPERTY_KEYWORDS.iter().for_each(|keyword| {
+                    keywords.remove(*keyword);
+                });
             }
-            if schema_object.contains_key("patternProperties") {
-                properties_updater(&mut self.pattern_properties, schema_object, "patternProperties");
+            self.add_extra_keywords(&schema);
+            if let Some(property_count) = self.property_count.as_mut() {
+                property_count.add_schema(&schema, "minProperties", "maxProperties");
             }
-            if schema_object.contains_key("required") {
-                if let Value::Array(required_fields) = &schema_object["required"] {
-                    if required_fields.len() == 0 {
-                        // if the input schema object has required fields being empty, that means 
-                        // including empty required fields in the schema is the desired behavior
-                        // and should be followed
-                        self.include_empty_required = true;
-                    }
-                    if self.required_properties.is_none() {
-                        let required_fields_set: HashSet<String> = required_fields.iter().map(|v| v.as_str().unwrap().to_string()).collect();
-                        self.required_properties = Some(required_fields_set);
-                    } else {
-                        // take the intersection
-                        self.required_properties.as_mut().unwrap().retain(|p| required_fields.contains(&Value::String(p.to_string())));
-                    }
+            return;
+        }
+        if is_discriminator_detection_enabled(&self.context.config) {
+            if let Some(mut variants) = self.variants.take() {
+                variants.add_schema(schema, &self.context, &|| self.to_merged_schema());
+                if !variants.is_empty() {
+                    self.variants = Some(variants);
                 }
             }
+            // the discriminator is only constant within each variant
+            let schema = DiscriminatedVariants::remove_discriminator_consts(schema, &self.context.config);
+            self.add_merged_schema(&schema);
         } else {
-            panic!("Invalid schema type - must be a valid JSON object")
+            self.add_merged_schema(schema);
         }
     }
 
     fn to_schema(&self) -> Value {
+        let schema = self.to_merged_schema();
+        match (&self.variants, &self.map_values) {
+            (Some(variants), None) => variants.to_schema(schema, &self.context.config),
+            _ => schema,
+        }
+    }
+}
+
+impl ObjectStrategy {
+    /// Generate the schema of all the objects merged together, regardless of their variants
+    fn to_merged_schema(&self) -> Value {
         let mut schema = self.extra_keywords.clone();
         schema["type"] = "object".into();
+        if self.context.is_at_max_depth() {
+            // the values of all properties are nested deeper than the maximum depth
+            schema["additionalProperties"] = self.context.max_depth_placeholder();
+            if let Some(property_count) = &self.property_count {
+                property_count.to_schema(&mut schema, "minProperties", "maxProperties");
+            }
+            return schema;
+        }
         if self.properties.len() > 0 {
             schema["properties"] = self.properties_to_schema(&self.properties);
         }
-        if self.pattern_properties.len() > 0 {
-            schema["patternProperties"] = self.properties_to_schema(&self.pattern_properties);
+        if !self.pattern_properties.is_empty() {
+            let mut schema_patterns = json!({});
+            self.pattern_properties.iter().for_each(|p| {
+                schema_patterns[&p.pattern] = p.node.to_schema();
+            });
+            schema["patternProperties"] = schema_patterns;
+        }
+        if let Some(property_count) = &self.property_count {
+            property_count.to_schema(&mut schema, "minProperties", "maxProperties");
+        }
+        let config = &self.context.config;
+        if config.strict_objects && self.map_values.is_none() && !matches_any_path(&self.context.path, &config.open_objects) {
+            // "additionalProperties" from the extra keywords takes precedence
+            schema.as_object_mut().unwrap().entry("additionalProperties").or_insert(false.into());
         }
-        if self.required_properties.is_some() || self.include_empty_required {
+        if let Some(map_values) = &self.map_values {
+            schema["additionalProperties"] = map_values.to_schema();
+            schema.as_object_mut().unwrap().remove("properties");
+            if let Some(map_keys) = &self.map_keys {
+                map_keys.to_schema(&mut schema);
+            }
+            // the keys of a map are dynamic, so none of them are required
+            schema.as_object_mut().unwrap().remove("required");
+        } else if let Some(required_props) = self.required_properties_over_threshold() {
+            if !required_props.is_empty() || self.include_empty_required {
+                schema["required"] = required_props.into();
+            } else {
+                schema.as_object_mut().unwrap().remove("required");
+            }
+        } else if self.required_properties.is_some() || self.include_empty_required {
             let mut required_props: Vec<String>;
             if let Some(required_properties) = &self.required_properties {
                 required_props = required_properties.iter().map(|p| p.to_string()).collect();
@@ -155,11 +343,169 @@ impl SchemaStrategy for ObjectStrategy {
         } else {
             schema.as_object_mut().unwrap().remove("required");
         }
+        if let (Some(property_counts), None) = (&self.property_counts, &self.map_values) {
+            property_counts.to_schema(&mut schema);
+        }
         schema
     }
-}
 
-impl ObjectStrategy {
+    /// Merge the schema into the properties of the object, which are shared by all variants
+    fn add_merged_schema(&mut self, schema: &Value) {
+        if let Value::Object(schema_object) = schema {
+            self.add_extra_keywords(schema);
+            if let Some(property_count) = self.property_count.as_mut() {
+                property_count.add_schema(schema, "minProperties", "maxProperties");
+            }
+
+            // update the internal properties and pattern_properties with the schema_object,
+            // creating schema node as needed for each property
+            let mut pattern_matched_properties = HashSet::new();
+            if let Some(schema_properties) = schema_object.get("properties").and_then(Value::as_object) {
+                schema_properties.iter().for_each(|(prop, sub_schema)| {
+                    // new properties go to the pattern property they match, as with objects
+                    if !self.properties.contains_key(prop) {
+                        if let Some(node) = self.find_pattern_property(prop) {
+                            node.add_schema(DataType::Schema(sub_schema));
+                            pattern_matched_properties.insert(prop.to_string());
+                            return;
+                        }
+                    }
+                    if let Some(node) = self.property_node(prop) {
+                        node.add_schema(DataType::Schema(sub_schema));
+                    }
+                });
+            }
+            if let Some(schema_patterns) = schema_object.get("patternProperties").and_then(Value::as_object) {
+                schema_patterns.iter().for_each(|(pattern, sub_schema)| {
+                    let idx = self.pattern_properties.iter().position(|p| &p.pattern == pattern)
+                        .unwrap_or_else(|| {
+                            self.pattern_properties.push(PatternProperty::new(pattern, &self.context));
+                            self.pattern_properties.len() - 1
+                        });
+                    self.pattern_properties[idx].node.add_schema(DataType::Schema(sub_schema));
+                });
+            }
+            if schema_object.contains_key("required") {
+                if let Value::Array(required_fields) = &schema_object["required"] {
+                    if required_fields.len() == 0 {
+                        // if the input schema object has required fields being empty, that means 
+                        // including empty required fields in the schema is the desired behavior
+                        // and should be followed
+                        self.include_empty_required = true;
+                    }
+                    if self.required_properties.is_none() {
+                        let required_fields_set: HashSet<String> = required_fields.iter()
+                            .map(|v| v.as_str().unwrap().to_string())
+                            .filter(|prop| !pattern_matched_properties.contains(prop))
+                            .collect();
+                        self.required_properties = Some(required_fields_set);
+                    } else {
+                        // take the intersection
+                        let required_properties = self.required_properties.as_mut().unwrap();
+                        let required_count = required_properties.len();
+                        required_properties.retain(|p| required_fields.contains(&Value::String(p.to_string())));
+                        if required_properties.len() < required_count {
+                            self.context.mark_structure_changed();
+                        }
+                    }
+                }
+            }
+            if let Some(property_counts) = self.property_counts.as_mut() {
+                property_counts.add_schema(schema_object);
+            }
+            if self.map_keys.is_some() {
+                self.add_map_schema(schema_object);
+            }
+        } else {
+            panic!("Invalid schema type - must be a valid JSON object")
+        }
+    }
+
+    /// Route the lexemes of the property values to the nodes the values were added to
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        let Lexeme::Object(entries) = lexeme else {
+            return;
+        };
+        if let Some(variants) = self.variants.as_mut() {
+            variants.add_lexeme(lexeme);
+        }
+        entries.iter().for_each(|(prop, value)| {
+            let node = if let Some(node) = self.properties.get_mut(prop) {
+                Some(node)
+            } else if let Some(idx) = self.pattern_properties.iter().position(|p| p.is_match(prop)) {
+                Some(&mut self.pattern_properties[idx].node)
+            } else {
+                self.map_values.as_mut()
+            };
+            if let Some(node) = node {
+                node.add_lexeme(value);
+            }
+        });
+    }
+
+    /// Get the properties that are present in at least the threshold fraction of the objects,
+    /// or None if no object has been counted, e.g. when the schema was only built from schemas
+    /// without counts, in which case the required properties are the intersection as usual
+    fn required_properties_over_threshold(&self) -> Option<Vec<String>> {
+        let property_counts = self.property_counts.as_ref().filter(|counts| counts.objects > 0)?;
+        let threshold = self.context.config.required_threshold?;
+        let min_count = threshold * property_counts.objects as f64;
+        let mut required_props: Vec<String> = property_counts.properties.iter()
+            .filter(|(prop, count)| **count as f64 >= min_count && self.properties.contains_key(*prop))
+            .map(|(prop, _)| prop.to_string())
+            .collect();
+        required_props.sort();
+        Some(required_props)
+    }
+
+    /// Find the node of the first pattern property that matches the property name. The pattern
+    /// properties from the configuration are only added to the object once a property matches them.
+    fn find_pattern_property(&mut self, prop: &str) -> Option<&mut SchemaNode> {
+        if let Some(idx) = self.pattern_properties.iter().position(|p| p.is_match(prop)) {
+            return Some(&mut self.pattern_properties[idx].node);
+        }
+        let regex = self.context.config.pattern_properties.iter().find(|regex| regex.is_match(prop))?;
+        self.pattern_properties.push(PatternProperty::new(regex.as_str(), &self.context));
+        self.pattern_properties.last_mut().map(|p| &mut p.node)
+    }
+
+    /// Merge in the keys and values of the schema when map detection is enabled. The schema
+    /// of a map has no "properties", but the merged schema of its values as "additionalProperties".
+    fn add_map_schema(&mut self, schema_object: &Map<String, Value>) {
+        let is_map_schema = !schema_object.contains_key("properties")
+            && schema_object.get("additionalProperties").is_some_and(Value::is_object);
+        if let Some(map_keys) = self.map_keys.as_mut() {
+            if is_map_schema {
+                map_keys.add_schema(schema_object.get("propertyNames").unwrap_or(&Value::Null));
+            } else if let Some(Value::Object(schema_properties)) = schema_object.get("properties") {
+                schema_properties.keys().for_each(|prop| map_keys.add_key(prop));
+            }
+        }
+        if is_map_schema {
+            let map_values = self.map_values
+                .get_or_insert_with(|| SchemaNode::new(self.context.child(ITEMS_PATH_SEGMENT)));
+            map_values.add_schema(DataType::Schema(&schema_object["additionalProperties"]));
+        }
+        self.detect_map();
+    }
+
+    /// Collapse all properties into the merged schema of the map values once the
+    /// object is detected to be a map
+    fn detect_map(&mut self) {
+        let Some(map_keys) = &self.map_keys else {
+            return;
+        };
+        if self.map_values.is_none() && !map_keys.is_map(self.properties.len(), &self.context.config) {
+            return;
+        }
+        let map_values = self.map_values
+            .get_or_insert_with(|| SchemaNode::new(self.context.child(ITEMS_PATH_SEGMENT)));
+        self.properties.drain().for_each(|(_, node)| {
+            map_values.add_schema(DataType::SchemaNode(&node));
+        });
+        self.required_properties = None;
+    }
+
     fn properties_to_schema(&self, properties: &HashMap<String, SchemaNode>) -> Value {
         let mut schema_properties = json!({});
         properties.iter().for_each(|(prop, node)| {
@@ -167,4 +513,11 @@ impl ObjectStrategy {
         });
         schema_properties
     }
-}
\ No newline at end of file
+}
+fn is_map_detection_enabled(config: &SchemaConfig) -> bool {
+    config.map_key_shapes || config.map_key_threshold.is_some()
+}
+
+pub fn is_discriminator_detection_enabled(config: &SchemaConfig) -> bool {
+    config.discriminated_unions || config.discriminator_property.is_some()
+}
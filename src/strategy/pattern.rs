@@ -0,0 +1,269 @@
+use serde_json::Value;
+
+// The maximum number of character runs a string can be made of for it to be generalized
+// into a pattern, strings with more runs than this (e.g. free form text) are considered
+// too diverse for a pattern to be meaningful.
+const MAX_PATTERN_TOKENS: usize = 16;
+
+const DIGIT: u8 = 0b001;
+const UPPER: u8 = 0b010;
+const LOWER: u8 = 0b100;
+
+// Characters that have to be escaped to be matched literally by an ECMA 262 regex
+const REGEX_SYNTAX_CHARACTERS: &str = r"\^$.|?*+()[]{}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    /// A set of the DIGIT, UPPER and LOWER character classes
+    Class(u8),
+    /// Any other character, which is matched literally
+    Literal(char),
+}
+
+impl CharClass {
+    fn of(c: char) -> Self {
+        if c.is_ascii_digit() {
+            CharClass::Class(DIGIT)
+        } else if c.is_ascii_uppercase() {
+            CharClass::Class(UPPER)
+        } else if c.is_ascii_lowercase() {
+            CharClass::Class(LOWER)
+        } else {
+            CharClass::Literal(c)
+        }
+    }
+
+    /// Get the narrowest character class that covers both classes, literal characters
+    /// only generalize with themselves
+    fn merge(self, other: CharClass) -> Option<CharClass> {
+        match (self, other) {
+            (CharClass::Class(a), CharClass::Class(b)) => Some(CharClass::Class(a | b)),
+            (a, b) if a == b => Some(a),
+            _ => None,
+        }
+    }
+
+    fn from_regex(class: &str) -> Option<CharClass> {
+        match class {
+            r"\d" => Some(CharClass::Class(DIGIT)),
+            "[A-Z]" => Some(CharClass::Class(UPPER)),
+            "[a-z]" => Some(CharClass::Class(LOWER)),
+            "[A-Za-z]" => Some(CharClass::Class(UPPER | LOWER)),
+            "[A-Z0-9]" => Some(CharClass::Class(UPPER | DIGIT)),
+            "[a-z0-9]" => Some(CharClass::Class(LOWER | DIGIT)),
+            "[A-Za-z0-9]" => Some(CharClass::Class(UPPER | LOWER | DIGIT)),
+            _ => None,
+        }
+    }
+
+    fn to_regex(self) -> String {
+        match self {
+            CharClass::Class(DIGIT) => r"\d".to_string(),
+            CharClass::Class(UPPER) => "[A-Z]".to_string(),
+            CharClass::Class(LOWER) => "[a-z]".to_string(),
+            CharClass::Class(class) if class == UPPER | LOWER => "[A-Za-z]".to_string(),
+            CharClass::Class(class) if class == UPPER | DIGIT => "[A-Z0-9]".to_string(),
+            CharClass::Class(class) if class == LOWER | DIGIT => "[a-z0-9]".to_string(),
+            CharClass::Class(_) => "[A-Za-z0-9]".to_string(),
+            CharClass::Literal(c) if REGEX_SYNTAX_CHARACTERS.contains(c) => format!(r"\{c}"),
+            CharClass::Literal(c) => c.to_string(),
+        }
+    }
+}
+
+/// A run of characters of the same class, repeated between min and max times
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    class: CharClass,
+    min: usize,
+    max: usize,
+}
+
+impl Token {
+    fn merge(&mut self, other: &Token) -> bool {
+        if let Some(class) = self.class.merge(other.class) {
+            self.class = class;
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn to_regex(&self) -> String {
+        let class = self.class.to_regex();
+        if self.min == self.max && self.min == 1 {
+            class
+        } else if self.min == self.max {
+            format!("{class}{{{}}}", self.min)
+        } else {
+            format!("{class}{{{},{}}}", self.min, self.max)
+        }
+    }
+}
+
+/// Generalization of the observed strings into a regex pattern. Every string is broken
+/// into runs of digits, upper case letters, lower case letters and literal separators,
+/// strings with the same sequence of runs are generalized into one pattern with length
+/// ranges for each run (e.g. "INV-000123" becomes `^[A-Z]{3}-\d{6}$`).
+#[derive(Debug, Clone, Default)]
+pub enum StringPattern {
+    /// No strings have been observed yet
+    #[default]
+    Empty,
+    Shape(Vec<Token>),
+    /// The observed strings are too diverse to be described by one pattern
+    Diverse,
+}
+
+impl StringPattern {
+    pub fn add_string(&mut self, s: &str) {
+        if let StringPattern::Diverse = self {
+            return;
+        }
+        match StringPattern::tokenize(s) {
+            Some(tokens) => self.merge_tokens(tokens),
+            None => *self = StringPattern::Diverse,
+        }
+    }
+
+    /// Merge in the "pattern" keyword of the schema. Patterns that were not generated by
+    /// this generalization, or a schema without any pattern, can not be generalized further.
+    pub fn add_schema(&mut self, schema: &Value) {
+        match schema["pattern"].as_str().and_then(StringPattern::parse) {
+            Some(tokens) => self.merge_tokens(tokens),
+            None => *self = StringPattern::Diverse,
+        }
+    }
+
+    pub fn to_schema(&self, schema: &mut Value) {
+        match self {
+            StringPattern::Empty => (),
+            StringPattern::Shape(tokens) => {
+                let pattern: String = tokens.iter().map(|token| token.to_regex()).collect();
+                schema["pattern"] = format!("^{pattern}$").into();
+            },
+            StringPattern::Diverse => {
+                // this is done in case there's a conflicting pattern from extra keywords
+                schema.as_object_mut().unwrap().remove("pattern");
+            },
+        }
+    }
+
+    fn merge_tokens(&mut self, tokens: Vec<Token>) {
+        match self {
+            StringPattern::Empty => *self = StringPattern::Shape(tokens),
+            StringPattern::Shape(current) => {
+                let is_mergeable = current.len() == tokens.len()
+                    && current.iter_mut().zip(tokens.iter()).all(|(token, other)| token.merge(other));
+                if !is_mergeable {
+                    *self = StringPattern::Diverse;
+                }
+            },
+            StringPattern::Diverse => (),
+        }
+    }
+
+    fn tokenize(s: &str) -> Option<Vec<Token>> {
+        let mut tokens: Vec<Token> = vec![];
+        for c in s.chars() {
+            let class = CharClass::of(c);
+            match tokens.last_mut() {
+                Some(token) if token.class == class => {
+                    token.min += 1;
+                    token.max += 1;
+                },
+                _ => {
+                    if tokens.len() == MAX_PATTERN_TOKENS {
+                        return None;
+                    }
+                    tokens.push(Token { class, min: 1, max: 1 });
+                },
+            }
+        }
+        Some(tokens)
+    }
+
+    /// Parse a pattern generated by `to_schema` back into its tokens
+    fn parse(pattern: &str) -> Option<Vec<Token>> {
+        let body = pattern.strip_prefix('^')?.strip_suffix('$')?;
+        let mut chars = body.chars().peekable();
+        let mut tokens: Vec<Token> = vec![];
+        while let Some(c) = chars.next() {
+            let class = match c {
+                '\\' => match chars.next()? {
+                    'd' => CharClass::Class(DIGIT),
+                    escaped => CharClass::Literal(escaped),
+                },
+                '[' => {
+                    let mut class = String::from("[");
+                    for c in chars.by_ref() {
+                        class.push(c);
+                        if c == ']' {
+                            break;
+                        }
+                    }
+                    CharClass::from_regex(&class)?
+                },
+                c if REGEX_SYNTAX_CHARACTERS.contains(c) => return None,
+                c => CharClass::Literal(c),
+            };
+
+            let (mut min, mut max) = (1, 1);
+            if chars.peek() == Some(&'{') {
+                chars.next();
+                let quantifier: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let mut bounds = quantifier.split(',').map(|bound| bound.parse::<usize>());
+                min = bounds.next()?.ok()?;
+                max = bounds.next().unwrap_or(Ok(min)).ok()?;
+            }
+            tokens.push(Token { class, min, max });
+        }
+        if tokens.len() > MAX_PATTERN_TOKENS {
+            return None;
+        }
+        Some(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pattern_of(strings: &[&str]) -> Value {
+        let mut pattern = StringPattern::default();
+        strings.iter().for_each(|s| pattern.add_string(s));
+        let mut schema = json!({});
+        pattern.to_schema(&mut schema);
+        schema
+    }
+
+    #[test]
+    fn test_pattern_generalizes_identifiers() {
+        assert_eq!(pattern_of(&["INV-000123", "INV-42"]), json!({"pattern": r"^[A-Z]{3}-\d{2,6}$"}));
+        assert_eq!(pattern_of(&["2024Q1", "2023Q4"]), json!({"pattern": r"^\d{4}[A-Z]\d$"}));
+    }
+
+    #[test]
+    fn test_pattern_generalizes_mixed_classes() {
+        assert_eq!(pattern_of(&["ab.1", "CD.X"]), json!({"pattern": r"^[A-Za-z]{2}\.[A-Z0-9]$"}));
+    }
+
+    #[test]
+    fn test_pattern_is_dropped_for_diverse_strings() {
+        assert_eq!(pattern_of(&["INV-000123", "hello world"]), json!({}));
+    }
+
+    #[test]
+    fn test_pattern_round_trips_through_schema() {
+        let schema = pattern_of(&["a+b_12", "ab+c_3"]);
+        let mut pattern = StringPattern::default();
+        pattern.add_schema(&schema);
+        pattern.add_string("xyz+z_456");
+        let mut merged = json!({});
+        pattern.to_schema(&mut merged);
+        assert_eq!(merged, json!({"pattern": r"^[a-z]{1,3}\+[a-z]_\d{1,3}$"}));
+    }
+}
@@ -1,8 +1,17 @@
 use serde_json::{Value, json};
 use simd_json;
-use simd_json::prelude::TypedScalarValue;
-
-use crate::strategy::base::{SchemaStrategy, ScalarSchemaStrategy};
+use simd_json::prelude::{TypedScalarValue, ValueAsScalar};
+
+use crate::config::EncodedScalars;
+use crate::lexeme::Lexeme;
+use crate::node::NodeContext;
+use crate::strategy::base::{SchemaStrategy, ScalarSchemaStrategy, SizeRange};
+use crate::strategy::examples::ExampleSample;
+use crate::strategy::encoded::{EncodedScalar, ENCODED_TYPE_KEYWORD};
+use crate::strategy::format::NumberFormat;
+use crate::strategy::timestamp::EpochTimestamp;
+use crate::strategy::decimal::DecimalSize;
+use crate::strategy::pattern::StringPattern;
 
 #[derive(Debug)]
 pub struct NullStrategy {
@@ -48,12 +57,17 @@ impl SchemaStrategy for NullStrategy {
 #[derive(Debug)]
 pub struct BooleanStrategy {
     extra_keywords: Value,
+    // only collected when examples are enabled
+    examples: Option<ExampleSample>,
+    context: NodeContext,
 }
 
 impl BooleanStrategy {
-    pub fn new() -> Self {
+    pub fn new(context: &NodeContext) -> Self {
         BooleanStrategy {
-            extra_keywords: json!({})
+            extra_keywords: json!({}),
+            examples: new_example_sample(context),
+            context: context.clone(),
         }
     }
 }
@@ -62,6 +76,15 @@ impl ScalarSchemaStrategy for BooleanStrategy {
     fn js_type() -> &'static str {
         "boolean"
     }
+
+    fn to_schema(&self) -> Value {
+        let mut schema = SchemaStrategy::to_schema(self);
+        schema["type"] = Value::String(Self::js_type().to_string());
+        if let Some(examples) = &self.examples {
+            examples.to_schema(&mut schema);
+        }
+        schema
+    }
 }
 
 impl SchemaStrategy for BooleanStrategy {
@@ -74,27 +97,50 @@ impl SchemaStrategy for BooleanStrategy {
     }
 
     fn match_schema(schema: &Value) -> bool {
-        schema["type"] == "boolean"
+        schema["type"] == "boolean" && schema.get(ENCODED_TYPE_KEYWORD).is_none()
     }
 
     fn match_object(object: &simd_json::BorrowedValue) -> bool {
         object.is_bool()
     }
 
-    fn add_object(&mut self, _object: &simd_json::BorrowedValue) {
-        ()
+    fn add_schema(&mut self, schema: &Value) {
+        self.add_extra_keywords(schema);
+        if let Some(examples) = self.examples.as_mut() {
+            examples.add_schema(schema);
+        }
+    }
+
+    fn add_object(&mut self, object: &simd_json::BorrowedValue) {
+        if let Some(examples) = self.examples.as_mut() {
+            examples.add_object(object, &self.context);
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct StringStrategy {
     extra_keywords: Value,
+    // only tracked when size constraints are enabled
+    length: Option<SizeRange>,
+    // only tracked when string patterns are enabled
+    pattern: Option<StringPattern>,
+    // only collected when examples are enabled
+    examples: Option<ExampleSample>,
+    // only tracked when encoded scalars are detected
+    encoded: Option<EncodedScalar>,
+    context: NodeContext,
 }
 
 impl StringStrategy {
-    pub fn new() -> Self {
+    pub fn new(context: &NodeContext) -> Self {
         StringStrategy {
-            extra_keywords: json!({})
+            extra_keywords: json!({}),
+            length: context.config.size_constraints.then(SizeRange::default),
+            pattern: context.config.string_patterns.then(StringPattern::default),
+            examples: new_example_sample(context),
+            encoded: (context.config.encoded_scalars != EncodedScalars::Ignore).then(EncodedScalar::default),
+            context: context.clone(),
         }
     }
 }
@@ -109,15 +155,45 @@ impl SchemaStrategy for StringStrategy {
     }
 
     fn match_schema(schema: &Value) -> bool {
-        schema["type"] == "string"
+        // strings that are coerced into their encoded type are still strings
+        schema["type"] == "string" || schema.get(ENCODED_TYPE_KEYWORD).is_some()
     }
 
     fn match_object(object: &simd_json::BorrowedValue) -> bool {
         object.is_str()
     }
 
-    fn add_object(&mut self, _object: &simd_json::BorrowedValue) {
-        ()
+    fn add_schema(&mut self, schema: &Value) {
+        self.add_extra_keywords(schema);
+        if let Some(length) = self.length.as_mut() {
+            length.add_schema(schema, "minLength", "maxLength");
+        }
+        if let Some(pattern) = self.pattern.as_mut() {
+            pattern.add_schema(schema);
+        }
+        if let Some(examples) = self.examples.as_mut() {
+            examples.add_schema(schema);
+        }
+        if let Some(encoded) = self.encoded.as_mut() {
+            encoded.add_schema(schema);
+        }
+    }
+
+    fn add_object(&mut self, object: &simd_json::BorrowedValue) {
+        if let simd_json::BorrowedValue::String(s) = object {
+            if let Some(length) = self.length.as_mut() {
+                length.add_size(s.chars().count());
+            }
+            if let Some(pattern) = self.pattern.as_mut() {
+                pattern.add_string(s);
+            }
+            if let Some(encoded) = self.encoded.as_mut() {
+                encoded.add_string(s);
+            }
+        }
+        if let Some(examples) = self.examples.as_mut() {
+            examples.add_object(object, &self.context);
+        }
     }
 }
 
@@ -125,23 +201,69 @@ impl ScalarSchemaStrategy for StringStrategy {
     fn js_type() -> &'static str {
         "string"
     }
+
+    fn to_schema(&self) -> Value {
+        let mut schema = SchemaStrategy::to_schema(self);
+        schema["type"] = Value::String(Self::js_type().to_string());
+        if let Some(length) = &self.length {
+            length.to_schema(&mut schema, "minLength", "maxLength");
+        }
+        if let Some(pattern) = &self.pattern {
+            pattern.to_schema(&mut schema);
+        }
+        if let Some(examples) = &self.examples {
+            examples.to_schema(&mut schema);
+        }
+        if let Some(encoded) = &self.encoded {
+            encoded.to_schema(&mut schema, self.context.config.encoded_scalars);
+        }
+        schema
+    }
 }
 
 #[derive(Debug)]
 pub struct NumberStrategy {
     number_type: &'static str,
     extra_keywords: Value,
+    // only collected when examples are enabled
+    examples: Option<ExampleSample>,
+    // only tracked when number formats are enabled
+    format: Option<NumberFormat>,
+    // only tracked when epoch timestamps are detected for the field
+    timestamp: Option<EpochTimestamp>,
+    // only tracked when decimal precision is enabled
+    decimal: Option<DecimalSize>,
+    context: NodeContext,
 }
 
 impl NumberStrategy {
-    pub fn new() -> Self {
+    pub fn new(context: &NodeContext) -> Self {
         NumberStrategy {
             number_type: "integer",
             extra_keywords: json!({}),
+            examples: new_example_sample(context),
+            format: context.config.number_formats.then(NumberFormat::default),
+            timestamp: is_timestamp_detection_enabled(context).then(EpochTimestamp::default),
+            decimal: context.config.decimal_precision.then(DecimalSize::default),
+            context: context.clone(),
         }
     }
 }
 
+/// Check if epoch timestamps are detected for the field, which is the case for all numeric
+/// fields when no name hints are configured, or only the ones whose name ends with a hint
+fn is_timestamp_detection_enabled(context: &NodeContext) -> bool {
+    let config = &context.config;
+    if !config.epoch_timestamps {
+        return false;
+    }
+    if config.timestamp_name_hints.is_empty() {
+        return true;
+    }
+    let field_name = context.field_name().unwrap_or_default().to_lowercase();
+    config.timestamp_name_hints.iter().any(|hint| field_name.ends_with(&hint.to_lowercase()))
+}
+
 impl ScalarSchemaStrategy for NumberStrategy {
     fn js_type() -> &'static str {
         "integer|number"
@@ -150,22 +272,77 @@ impl ScalarSchemaStrategy for NumberStrategy {
     fn to_schema(&self) -> Value {
         let mut schema = SchemaStrategy::to_schema(self);
         schema["type"] = Value::String(self.number_type.to_string());
+        if let Some(examples) = &self.examples {
+            examples.to_schema(&mut schema);
+        }
+        if let Some(format) = &self.format {
+            format.to_schema(&mut schema, self.number_type);
+        }
+        if let Some(timestamp) = &self.timestamp {
+            timestamp.to_schema(&mut schema);
+        }
+        if let Some(decimal) = &self.decimal {
+            decimal.to_schema(&mut schema);
+        }
         schema
     }
 }
 
+impl NumberStrategy {
+    pub fn add_lexeme(&mut self, lexeme: &Lexeme) {
+        if let (Some(decimal), Lexeme::Number(number)) = (self.decimal.as_mut(), lexeme) {
+            decimal.add_lexeme(number);
+        }
+    }
+
+    /// Check if the number is inferred as an integer, which includes floats without a fractional
+    /// part (e.g. 1.0, or integers too large for 64 bits) when integral floats are enabled
+    fn is_integer(&self, object: &simd_json::BorrowedValue) -> bool {
+        match object.as_f64() {
+            Some(number) if object.is_f64() => {
+                self.context.config.integral_floats && number.is_finite() && number.fract() == 0.0
+            },
+            _ => true,
+        }
+    }
+}
+
 impl SchemaStrategy for NumberStrategy {
     fn add_schema(&mut self, schema: &Value) {
         self.add_extra_keywords(schema);
         // "number" takes precedence over "integer" if both are present
-        if schema["type"] == "number" {
+        if schema["type"] == "number" && self.number_type != "number" {
             self.number_type = "number";
+            self.context.mark_structure_changed();
+        }
+        if let Some(examples) = self.examples.as_mut() {
+            examples.add_schema(schema);
+        }
+        if let Some(format) = self.format.as_mut() {
+            format.add_schema(schema);
+        }
+        if let Some(timestamp) = self.timestamp.as_mut() {
+            timestamp.add_schema(schema);
+        }
+        if let Some(decimal) = self.decimal.as_mut() {
+            decimal.add_schema(schema);
         }
     }
 
     fn add_object(&mut self, object: &simd_json::BorrowedValue) {
-        if object.is_f64() {
+        let is_integer = self.is_integer(object);
+        if !is_integer && self.number_type != "number" {
             self.number_type = "number";
+            self.context.mark_structure_changed();
+        }
+        if let Some(examples) = self.examples.as_mut() {
+            examples.add_object(object, &self.context);
+        }
+        if let Some(format) = self.format.as_mut() {
+            format.add_object(object, is_integer);
+        }
+        if let Some(timestamp) = self.timestamp.as_mut() {
+            timestamp.add_object(object);
         }
     }
 
@@ -178,7 +355,7 @@ impl SchemaStrategy for NumberStrategy {
     }
 
     fn match_schema(schema: &Value) -> bool {
-        schema["type"] == "number" || schema["type"] == "integer"
+        (schema["type"] == "number" || schema["type"] == "integer") && schema.get(ENCODED_TYPE_KEYWORD).is_none()
     }
 
     fn match_object(object: &simd_json::BorrowedValue) -> bool {
@@ -225,4 +402,8 @@ impl SchemaStrategy for TypelessStrategy {
     fn add_object(&mut self, _object: &simd_json::BorrowedValue) {
         ()
     }
-}
\ No newline at end of file
+}
+fn new_example_sample(context: &NodeContext) -> Option<ExampleSample> {
+    let max_examples = context.config.max_examples;
+    (max_examples > 0).then(|| ExampleSample::new(max_examples))
+}
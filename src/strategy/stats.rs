@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+const COUNT_KEYWORD: &str = "x-genson-count";
+const NULL_RATIO_KEYWORD: &str = "x-genson-null-ratio";
+const TYPE_DISTRIBUTION_KEYWORD: &str = "x-genson-type-distribution";
+
+/// NodeStats counts the values observed for a schema node, in total and per type. For
+/// a property, the total count is the number of objects the property was present in.
+/// The counts are emitted as annotations, and merged back from them when the schemas
+/// of different builders are combined.
+#[derive(Debug, Default, Clone)]
+pub struct NodeStats {
+    count: u64,
+    // keyed by JSON type, where integers are counted separately from other numbers
+    types: BTreeMap<String, u64>,
+}
+
+impl NodeStats {
+    pub fn add_object(&mut self, object: &simd_json::BorrowedValue) {
+        self.count += 1;
+        *self.types.entry(NodeStats::object_type(object).to_string()).or_insert(0) += 1;
+    }
+
+    /// Merge in the annotations of the schema, and remove them from it so that they
+    /// don't end up as extra keywords of the strategies
+    pub fn add_schema(&mut self, schema: &mut Value) {
+        let Value::Object(schema) = schema else {
+            return;
+        };
+        if let Some(count) = schema.remove(COUNT_KEYWORD).and_then(|count| count.as_u64()) {
+            self.count += count;
+        }
+        if let Some(Value::Object(types)) = schema.remove(TYPE_DISTRIBUTION_KEYWORD) {
+            types.iter().for_each(|(json_type, count)| {
+                *self.types.entry(json_type.to_string()).or_insert(0) += count.as_u64().unwrap_or(0);
+            });
+        }
+        // the ratio is derived from the counts
+        schema.remove(NULL_RATIO_KEYWORD);
+    }
+
+    pub fn to_schema(&self, schema: &mut Value) {
+        if self.count == 0 {
+            return;
+        }
+        let null_count = self.types.get("null").copied().unwrap_or(0);
+        schema[COUNT_KEYWORD] = self.count.into();
+        schema[NULL_RATIO_KEYWORD] = json!(null_count as f64 / self.count as f64);
+        schema[TYPE_DISTRIBUTION_KEYWORD] = json!(self.types);
+    }
+
+    fn object_type(object: &simd_json::BorrowedValue) -> &'static str {
+        match object {
+            simd_json::BorrowedValue::Static(simd_json::StaticNode::Null) => "null",
+            simd_json::BorrowedValue::Static(simd_json::StaticNode::Bool(_)) => "boolean",
+            simd_json::BorrowedValue::Static(simd_json::StaticNode::F64(_)) => "number",
+            simd_json::BorrowedValue::Static(_) => "integer",
+            simd_json::BorrowedValue::String(_) => "string",
+            simd_json::BorrowedValue::Array(_) => "array",
+            simd_json::BorrowedValue::Object(_) => "object",
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use serde_json::Value;
+use simd_json::prelude::ValueAsScalar;
+
+const SECONDS: u8 = 0b001;
+const MILLISECONDS: u8 = 0b010;
+const MICROSECONDS: u8 = 0b100;
+
+// Unix epoch seconds of 2000-01-01 and 2100-01-01, the window of plausible timestamps
+const MIN_EPOCH_SECONDS: f64 = 946_684_800.0;
+const MAX_EPOCH_SECONDS: f64 = 4_102_444_800.0;
+
+// The units of the timestamps, by their number per second
+const EPOCH_UNITS: [(u8, f64, &str); 3] = [
+    (SECONDS, 1.0, "epoch-s"),
+    (MILLISECONDS, 1e3, "epoch-ms"),
+    (MICROSECONDS, 1e6, "epoch-us"),
+];
+
+pub const TIMESTAMP_KEYWORD: &str = "x-genson-timestamp";
+
+/// EpochTimestamp tracks whether all the observed numbers are Unix epoch timestamps in
+/// the same unit, which is told apart by the magnitude of the numbers as the windows of
+/// plausible seconds, milliseconds and microseconds don't overlap
+#[derive(Debug, Clone)]
+pub struct EpochTimestamp {
+    // the set of units every number observed so far is a plausible timestamp in
+    units: u8,
+}
+
+impl Default for EpochTimestamp {
+    fn default() -> Self {
+        EpochTimestamp { units: SECONDS | MILLISECONDS | MICROSECONDS }
+    }
+}
+
+impl EpochTimestamp {
+    pub fn add_object(&mut self, object: &simd_json::BorrowedValue) {
+        let number = object.cast_f64().unwrap_or(f64::NAN);
+        EPOCH_UNITS.iter().for_each(|(unit, per_second, _)| {
+            let seconds = number / per_second;
+            if !(MIN_EPOCH_SECONDS..=MAX_EPOCH_SECONDS).contains(&seconds) {
+                self.units &= !unit;
+            }
+        });
+    }
+
+    /// Merge in the timestamp annotation of the schema, a schema without
+    /// the annotation describes numbers that aren't all timestamps
+    pub fn add_schema(&mut self, schema: &Value) {
+        self.units &= EPOCH_UNITS.iter()
+            .find(|(_, _, name)| schema[TIMESTAMP_KEYWORD] == *name)
+            .map_or(0, |(unit, _, _)| *unit);
+    }
+
+    pub fn to_schema(&self, schema: &mut Value) {
+        let unit = EPOCH_UNITS.iter().find(|(unit, _, _)| self.units & unit != 0);
+        if let Some((_, _, name)) = unit {
+            schema[TIMESTAMP_KEYWORD] = (*name).into();
+        } else {
+            schema.as_object_mut().unwrap().remove(TIMESTAMP_KEYWORD);
+        }
+    }
+}
@@ -1,11 +1,13 @@
-use genson_rs::{build_json_schema, get_builder, BuildConfig};
-use serde_json::json;
+use genson_rs::{build_json_schema, get_builder, try_build_json_schema, BuildConfig, BuildError, EncodedScalars, ExampleRedactor, NullableStyle, SchemaBuilder, SchemaConfig, Selector, MAX_NESTING_DEPTH};
+use regex::Regex;
+use serde_json::{json, Value};
 
 #[test]
 fn test_anyof_should_include_all_scalar_field_types() {
     let config = BuildConfig {
         delimiter: None,
         ignore_outer_array: false,
+        ..Default::default()
     };
     let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -44,6 +46,7 @@ fn test_field_should_be_optional_when_not_present_in_all_objects() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -76,6 +79,7 @@ fn test_field_should_be_required_when_present_in_all_objects() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -108,6 +112,7 @@ fn test_field_type_should_be_integer_if_all_values_are_ints() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -142,6 +147,7 @@ fn test_field_type_should_be_number_if_values_include_float() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -175,6 +181,7 @@ fn test_schema_should_include_all_fields_that_are_present() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -212,6 +219,7 @@ fn test_field_should_be_correct_object_type_when_its_nested_json() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -253,6 +261,7 @@ fn test_field_should_be_correct_array_type_when_its_an_array() {
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -289,6 +298,7 @@ fn test_field_should_be_correct_tuple_type_when_its_an_array_of_different_value_
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = json!(
@@ -328,6 +338,7 @@ fn test_schema_should_be_correct_when_building_from_multiple_objects() {
   let config = BuildConfig {
     delimiter: Some("\n".as_bytes()[0]),
     ignore_outer_array: false,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = r#"
@@ -367,6 +378,7 @@ fn test_json_schema_should_not_contain_outer_array_when_ignore_outer_array_confi
   let config = BuildConfig {
     delimiter: None,
     ignore_outer_array: true,
+    ..Default::default()
 };
   let mut builder = get_builder(None);
     let mut test_object = r#"
@@ -392,4 +404,1567 @@ fn test_json_schema_should_not_contain_outer_array_when_ignore_outer_array_confi
       ]
     });
     assert_eq!(schema, expected_schema);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_schema_should_include_size_constraints_when_enabled() {
+  let config = BuildConfig {
+    delimiter: Some("\n".as_bytes()[0]),
+    ignore_outer_array: false,
+    ..Default::default()
+};
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    size_constraints: true,
+    ..Default::default()
+  });
+    let mut test_object = r#"
+      {"name": "ab", "tags": [1, 2, 3]}
+      {"name": "abcde", "tags": []}
+      {"name": "héllo wörld", "tags": [4], "extra": true}
+    "#.to_string().into_bytes();
+    let schema = build_json_schema(&mut builder, &mut test_object, &config);
+
+    let expected_schema = json!({
+      "type": "object",
+      "minProperties": 2,
+      "maxProperties": 3,
+      "properties": {
+        "name": {
+          "type": "string",
+          "minLength": 2,
+          "maxLength": 11
+        },
+        "tags": {
+          "type": "array",
+          "minItems": 0,
+          "maxItems": 3,
+          "items": {
+            "type": "integer"
+          }
+        },
+        "extra": {
+          "type": "boolean"
+        }
+      },
+      "required": [
+        "name",
+        "tags"
+      ]
+    });
+    assert_eq!(schema, expected_schema);
+}
+
+#[test]
+fn test_size_constraints_should_merge_with_seed_schema() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    size_constraints: true,
+    ..Default::default()
+  });
+    builder.add_schema(json!({"type": "string", "minLength": 1, "maxLength": 4}));
+    let mut test_object = br#""abcdefgh""#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+
+    assert_eq!(builder.to_schema(), json!({"type": "string", "minLength": 1, "maxLength": 8}));
+}
+
+#[test]
+fn test_schema_should_include_string_pattern_when_enabled() {
+  let config = BuildConfig {
+    delimiter: Some("\n".as_bytes()[0]),
+    ignore_outer_array: false,
+    ..Default::default()
+};
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    string_patterns: true,
+    ..Default::default()
+  });
+    let mut test_object = r#"
+      {"invoice": "INV-000123", "quarter": "2024Q1", "note": "paid in full"}
+      {"invoice": "INV-000124", "quarter": "2023Q4", "note": "overdue"}
+      {"invoice": "INV-99", "quarter": "2022Q2", "note": "n/a"}
+    "#.to_string().into_bytes();
+    let schema = build_json_schema(&mut builder, &mut test_object, &config);
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "invoice": {
+          "type": "string",
+          "pattern": "^[A-Z]{3}-\\d{2,6}$"
+        },
+        "quarter": {
+          "type": "string",
+          "pattern": "^\\d{4}[A-Z]\\d$"
+        },
+        "note": {
+          "type": "string"
+        }
+      },
+      "required": [
+        "invoice",
+        "note",
+        "quarter"
+      ]
+    });
+    assert_eq!(schema, expected_schema);
+}
+
+#[test]
+fn test_schema_should_include_examples_when_enabled() {
+  let config = BuildConfig {
+    delimiter: Some("\n".as_bytes()[0]),
+    ignore_outer_array: false,
+    ..Default::default()
+};
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    max_examples: 2,
+    ..Default::default()
+  });
+    let mut test_object = r#"
+      {"name": "a", "age": 1, "active": true, "tags": ["x"]}
+      {"name": "b", "age": 2, "active": false, "tags": ["y"]}
+      {"name": "c", "age": 3.5, "active": true, "tags": ["z"]}
+      {"name": "a", "age": 1, "active": true, "tags": ["x"]}
+    "#.to_string().into_bytes();
+    let schema = build_json_schema(&mut builder, &mut test_object, &config);
+
+    let properties = &schema["properties"];
+    assert_eq!(properties["name"]["examples"].as_array().unwrap().len(), 2);
+    assert_eq!(properties["age"]["examples"].as_array().unwrap().len(), 2);
+    assert_eq!(properties["active"]["examples"].as_array().unwrap().len(), 2);
+    assert_eq!(properties["tags"]["items"]["examples"].as_array().unwrap().len(), 2);
+    assert_eq!(properties["age"]["type"], "number");
+
+    // the sample doesn't depend on how the records were chunked or ordered
+    let mut reversed_builder = SchemaBuilder::with_config(None, SchemaConfig {
+      max_examples: 2,
+      ..Default::default()
+    });
+    let mut reversed_object = r#"
+      {"name": "c", "age": 3.5, "active": true, "tags": ["z"]}
+      {"name": "a", "age": 1, "active": true, "tags": ["x"]}
+      {"name": "b", "age": 2, "active": false, "tags": ["y"]}
+    "#.to_string().into_bytes();
+    let reversed_schema = build_json_schema(&mut reversed_builder, &mut reversed_object, &config);
+    assert_eq!(schema, reversed_schema);
+}
+
+#[test]
+fn test_examples_should_be_masked_by_redactor() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    max_examples: 5,
+    example_redactor: Some(ExampleRedactor::new(|path, value| {
+      match path {
+        "/email" => Some(json!("***")),
+        "/secret" => None,
+        _ => Some(value),
+      }
+    })),
+    ..Default::default()
+  });
+    let mut test_object = br#"{"email": "a@b.c", "secret": "hunter2", "id": 7}"#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+
+    let schema = builder.to_schema();
+    assert_eq!(schema["properties"]["email"], json!({"type": "string", "examples": ["***"]}));
+    assert_eq!(schema["properties"]["secret"], json!({"type": "string"}));
+    assert_eq!(schema["properties"]["id"], json!({"type": "integer", "examples": [7]}));
+}
+
+#[test]
+fn test_objects_with_dynamic_keys_should_collapse_into_additional_properties() {
+  let config = BuildConfig {
+    delimiter: Some("\n".as_bytes()[0]),
+    ignore_outer_array: false,
+    ..Default::default()
+};
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    map_key_shapes: true,
+    map_key_threshold: Some(3),
+    map_property_names: true,
+    ..Default::default()
+  });
+    let mut test_object = r#"
+      {"by_user": {"1001": {"score": 1}}, "by_day": {"2024-01-01": 3}, "by_sku": {"SKU-1": true, "SKU-2": false}}
+      {"by_user": {"1002": {"score": 2}, "1003": {"score": 3}}, "by_day": {"2024-01-02": 4.5}, "by_sku": {"SKU-3": true}}
+      {"by_user": {}, "by_day": {}, "by_sku": {"SKU-4": true, "SKU-10": false}}
+    "#.to_string().into_bytes();
+    let schema = build_json_schema(&mut builder, &mut test_object, &config);
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "by_user": {
+          "type": "object",
+          "additionalProperties": {
+            "type": "object",
+            "properties": {
+              "score": {"type": "integer"}
+            },
+            "required": ["score"]
+          },
+          "propertyNames": {"pattern": "^\\d+$"}
+        },
+        "by_day": {
+          "type": "object",
+          "additionalProperties": {"type": "number"},
+          "propertyNames": {"pattern": "^\\d{4}-\\d{2}-\\d{2}$"}
+        },
+        "by_sku": {
+          "type": "object",
+          "additionalProperties": {"type": "boolean"},
+          "propertyNames": {"pattern": "^[A-Z]{3}-\\d{1,2}$"}
+        }
+      },
+      "required": ["by_day", "by_sku", "by_user"]
+    });
+    assert_eq!(schema, expected_schema);
+}
+
+#[test]
+fn test_properties_matching_configured_patterns_should_be_pattern_properties() {
+  let config = BuildConfig {
+    delimiter: Some("\n".as_bytes()[0]),
+    ignore_outer_array: false,
+    ..Default::default()
+};
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    pattern_properties: vec![Regex::new("^x-").unwrap()],
+    ..Default::default()
+  });
+    let mut test_object = r#"
+      {"name": "a", "x-trace": "abc", "nested": {"x-count": 1}}
+      {"name": "b", "x-debug": true, "nested": {"value": 2}}
+    "#.to_string().into_bytes();
+    let schema = build_json_schema(&mut builder, &mut test_object, &config);
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "name": {"type": "string"},
+        "nested": {
+          "type": "object",
+          "properties": {
+            "value": {"type": "integer"}
+          },
+          "patternProperties": {
+            "^x-": {"type": "integer"}
+          }
+        }
+      },
+      "patternProperties": {
+        "^x-": {"type": ["boolean", "string"]}
+      },
+      "required": ["name", "nested"]
+    });
+    assert_eq!(schema, expected_schema);
+}
+
+#[test]
+fn test_seed_pattern_properties_should_take_precedence_over_properties() {
+  let mut builder = get_builder(None);
+    builder.add_schema(json!({"type": "object", "patternProperties": {"^[0-9]+$": {}, "(?<=x)": {}}}));
+    let mut test_object = br#"{"1": "a", "22": 5, "name": "x"}"#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "name": {"type": "string"}
+      },
+      "patternProperties": {
+        "^[0-9]+$": {"type": ["integer", "string"]},
+        "(?<=x)": {}
+      },
+      "required": ["name"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_objects_should_be_closed_when_strict_objects_enabled() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    strict_objects: true,
+    open_objects: vec!["/items/*/metadata".to_string(), "/**/extra_*".to_string()],
+    ..Default::default()
+  });
+    let mut test_object = br#"{"items": [{"id": 1, "metadata": {"a": 1}}], "owner": {"name": "x", "extra_info": {"b": true}}}"#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+
+    let expected_schema = json!({
+      "type": "object",
+      "additionalProperties": false,
+      "properties": {
+        "items": {
+          "type": "array",
+          "items": {
+            "type": "object",
+            "additionalProperties": false,
+            "properties": {
+              "id": {"type": "integer"},
+              "metadata": {
+                "type": "object",
+                "properties": {"a": {"type": "integer"}},
+                "required": ["a"]
+              }
+            },
+            "required": ["id", "metadata"]
+          }
+        },
+        "owner": {
+          "type": "object",
+          "additionalProperties": false,
+          "properties": {
+            "name": {"type": "string"},
+            "extra_info": {
+              "type": "object",
+              "properties": {"b": {"type": "boolean"}},
+              "required": ["b"]
+            }
+          },
+          "required": ["extra_info", "name"]
+        }
+      },
+      "required": ["items", "owner"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_strict_objects_should_use_unevaluated_properties_for_newer_drafts() {
+  let mut builder = SchemaBuilder::with_config(Some("https://json-schema.org/draft/2020-12/schema"), SchemaConfig {
+    strict_objects: true,
+    ..Default::default()
+  });
+    let mut test_object = br#"{"owner": {"name": "x"}}"#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+
+    let expected_schema = json!({
+      "$schema": "https://json-schema.org/draft/2020-12/schema",
+      "type": "object",
+      "unevaluatedProperties": false,
+      "properties": {
+        "owner": {
+          "type": "object",
+          "unevaluatedProperties": false,
+          "properties": {"name": {"type": "string"}},
+          "required": ["name"]
+        }
+      },
+      "required": ["owner"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_tuple_detection_should_infer_heterogeneous_arrays_as_tuples() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    tuple_detection: true,
+    ..Default::default()
+  });
+    for row in [br#"{"pair": ["a", 1]}"#.to_vec(), br#"{"pair": ["b", 2.5]}"#.to_vec()] {
+        let mut row = row;
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "pair": {
+          "type": "array",
+          "items": [{"type": "string"}, {"type": "number"}]
+        }
+      },
+      "required": ["pair"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_tuple_detection_should_fall_back_to_lists() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    tuple_detection: true,
+    ..Default::default()
+  });
+    for row in [
+        br#"{"varying_length": ["a", 1], "varying_type": ["a", 1], "homogeneous": [1, 2]}"#.to_vec(),
+        br#"{"varying_length": ["b", 2, 3], "varying_type": [1, "a"], "homogeneous": [3]}"#.to_vec(),
+    ] {
+        let mut row = row;
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+
+    let list_schema = json!({
+      "type": "array",
+      "items": {"type": ["integer", "string"]}
+    });
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "varying_length": list_schema,
+        "varying_type": list_schema,
+        "homogeneous": {"type": "array", "items": {"type": "integer"}}
+      },
+      "required": ["homogeneous", "varying_length", "varying_type"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_nullable_style_should_apply_to_objects_and_scalars() {
+    let expected_schemas = [
+        (NullableStyle::TypeArray, json!({
+          "type": "object",
+          "properties": {
+            "owner": {"type": ["object", "null"], "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+            "name": {"type": ["string", "null"]}
+          },
+          "required": ["name", "owner"]
+        })),
+        (NullableStyle::AnyOf, json!({
+          "type": "object",
+          "properties": {
+            "owner": {"anyOf": [
+              {"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+              {"type": "null"}
+            ]},
+            "name": {"anyOf": [{"type": "string"}, {"type": "null"}]}
+          },
+          "required": ["name", "owner"]
+        })),
+        (NullableStyle::OpenApi, json!({
+          "type": "object",
+          "properties": {
+            "owner": {"type": "object", "nullable": true, "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+            "name": {"type": "string", "nullable": true}
+          },
+          "required": ["name", "owner"]
+        })),
+    ];
+
+    for (nullable_style, expected_schema) in expected_schemas {
+        let config = SchemaConfig { nullable_style, ..Default::default() };
+        let mut builder = SchemaBuilder::with_config(None, config.clone());
+        for row in [br#"{"owner": {"id": 1}, "name": "a"}"#.to_vec(), br#"{"owner": null, "name": null}"#.to_vec()] {
+            let mut row = row;
+            let object = simd_json::to_borrowed_value(&mut row).unwrap();
+            builder.add_object(&object);
+        }
+        assert_eq!(builder.to_schema(), expected_schema);
+
+        // the nullable schema should be merged back in the same style
+        let mut merged_builder = SchemaBuilder::with_config(None, config);
+        merged_builder.add_schema(builder.to_schema());
+        assert_eq!(merged_builder.to_schema(), expected_schema);
+    }
+}
+
+#[test]
+fn test_openapi_nullable_style_should_not_emit_null_or_type_arrays() {
+    let config = SchemaConfig { nullable_style: NullableStyle::OpenApi, ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(None, config.clone());
+    for row in [
+        br#"{"always_null": null, "nullable_mixed": 1, "mixed": 1}"#.to_vec(),
+        br#"{"always_null": null, "nullable_mixed": "a", "mixed": "a"}"#.to_vec(),
+        br#"{"always_null": null, "nullable_mixed": null, "mixed": "b"}"#.to_vec(),
+    ] {
+        let mut row = row;
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "always_null": {"type": "string", "nullable": true, "enum": [null]},
+        "nullable_mixed": {"anyOf": [
+          {"type": "integer", "nullable": true},
+          {"type": "string", "nullable": true}
+        ]},
+        "mixed": {"anyOf": [{"type": "integer"}, {"type": "string"}]}
+      },
+      "required": ["always_null", "mixed", "nullable_mixed"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+
+    // a field that was only null in one builder takes the types of the other builder
+    let mut merged_builder = SchemaBuilder::with_config(None, config);
+    merged_builder.add_schema(builder.to_schema());
+    assert_eq!(merged_builder.to_schema(), expected_schema);
+    merged_builder.add_schema(json!({"type": "object", "properties": {"always_null": {"type": "boolean"}}}));
+    assert_eq!(merged_builder.to_schema()["properties"]["always_null"], json!({"type": "boolean", "nullable": true}));
+}
+
+#[test]
+fn test_one_of_should_be_used_for_unions_disjoint_by_type() {
+  let config = SchemaConfig { one_of: true, ..Default::default() };
+  let mut builder = SchemaBuilder::with_config(None, config.clone());
+    for row in [br#"{"value": {"id": 1}}"#.to_vec(), br#"{"value": [1]}"#.to_vec(), br#"{"value": "a"}"#.to_vec()] {
+        let mut row = row;
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "value": {"oneOf": [
+          {"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+          {"type": "array", "items": {"type": "integer"}},
+          {"type": "string"}
+        ]}
+      },
+      "required": ["value"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+
+    let mut merged_builder = SchemaBuilder::with_config(None, config);
+    merged_builder.add_schema(builder.to_schema());
+    assert_eq!(merged_builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_discriminated_unions_should_infer_one_schema_per_variant() {
+  let config = SchemaConfig { discriminated_unions: true, ..Default::default() };
+  let mut builder = SchemaBuilder::with_config(None, config.clone());
+    for row in [
+        br#"{"kind": "click", "x": 1, "y": 2}"#.to_vec(),
+        br#"{"kind": "view", "page": "home"}"#.to_vec(),
+        br#"{"kind": "click", "x": 3, "y": 4, "button": "left"}"#.to_vec(),
+    ] {
+        let mut row = row;
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+
+    let expected_schema = json!({
+      "oneOf": [
+        {
+          "type": "object",
+          "properties": {
+            "kind": {"type": "string", "const": "click"},
+            "x": {"type": "integer"},
+            "y": {"type": "integer"},
+            "button": {"type": "string"}
+          },
+          "required": ["kind", "x", "y"]
+        },
+        {
+          "type": "object",
+          "properties": {
+            "kind": {"type": "string", "const": "view"},
+            "page": {"type": "string"}
+          },
+          "required": ["kind", "page"]
+        }
+      ]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+
+    // the variants should survive merging the schema into another builder
+    let mut merged_builder = SchemaBuilder::with_config(None, config);
+    merged_builder.add_schema(builder.to_schema());
+    assert_eq!(merged_builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_discriminated_unions_should_only_split_properties_with_several_values() {
+    let config = SchemaConfig { discriminated_unions: true, ..Default::default() };
+    let feature = |geometry: &str| format!(r#"{{"type": "Feature", "id": 1, "geometry": {}}}"#, geometry);
+    let point = feature(r#"{"type": "Point", "coordinates": [1.5, 2.5]}"#);
+    let line = feature(r#"{"type": "LineString", "coordinates": [[1.5, 2.5], [3.5, 4.5]]}"#);
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "type": {"type": "string"},
+        "id": {"type": "integer"},
+        "geometry": {"oneOf": [
+          {
+            "type": "object",
+            "properties": {
+              "type": {"type": "string", "const": "LineString"},
+              "coordinates": {"type": "array", "items": {"type": "array", "items": {"type": "number"}}}
+            },
+            "required": ["coordinates", "type"]
+          },
+          {
+            "type": "object",
+            "properties": {
+              "type": {"type": "string", "const": "Point"},
+              "coordinates": {"type": "array", "items": {"type": "number"}}
+            },
+            "required": ["coordinates", "type"]
+          }
+        ]}
+      },
+      "required": ["geometry", "id", "type"]
+    });
+
+    let mut builder = SchemaBuilder::with_config(None, config.clone());
+    for row in [&point, &line, &point] {
+        let mut row = row.as_bytes().to_vec();
+        builder.add_object(&simd_json::to_borrowed_value(&mut row).unwrap());
+    }
+    assert_eq!(builder.to_schema(), expected_schema);
+
+    // the variants are told apart when each builder has only seen one of them
+    let mut point_builder = SchemaBuilder::with_config(None, config.clone());
+    let mut line_builder = SchemaBuilder::with_config(None, config.clone());
+    for (builder, row) in [(&mut point_builder, &point), (&mut line_builder, &line)] {
+        let mut row = row.as_bytes().to_vec();
+        builder.add_object(&simd_json::to_borrowed_value(&mut row).unwrap());
+    }
+    assert_eq!(point_builder.to_schema()["properties"]["geometry"]["properties"]["type"], json!({"type": "string"}));
+    let mut merged_builder = SchemaBuilder::with_config(None, config);
+    merged_builder.add_builder(&point_builder);
+    merged_builder.add_builder(&line_builder);
+    assert_eq!(merged_builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_named_discriminator_should_fall_back_to_one_object_when_missing() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    discriminator_property: Some("op".to_string()),
+    nullable_style: NullableStyle::OpenApi,
+    ..Default::default()
+  });
+    let mut test_object = br#"[{"op": "add", "value": 1}, {"op": "remove", "path": "/a"}]"#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+    let schema = builder.to_schema();
+    // the variants are referred to from the mapping, and tagged with an "enum" as OpenAPI 3.0 has no "const"
+    assert_eq!(schema["items"], json!({
+      "oneOf": [{"$ref": "#/definitions/add"}, {"$ref": "#/definitions/remove"}],
+      "discriminator": {
+        "propertyName": "op",
+        "mapping": {"add": "#/definitions/add", "remove": "#/definitions/remove"}
+      }
+    }));
+    assert_eq!(schema["definitions"]["add"], json!({
+      "type": "object",
+      "properties": {"op": {"type": "string", "enum": ["add"]}, "value": {"type": "integer"}},
+      "required": ["op", "value"]
+    }));
+    assert_eq!(schema["definitions"]["remove"]["properties"]["op"], json!({"type": "string", "enum": ["remove"]}));
+
+    // the variants should survive merging the schema into another builder
+    let mut merged_builder = SchemaBuilder::with_config(None, SchemaConfig {
+      discriminator_property: Some("op".to_string()),
+      nullable_style: NullableStyle::OpenApi,
+      ..Default::default()
+    });
+    merged_builder.add_schema(schema.clone());
+    assert_eq!(merged_builder.to_schema(), schema);
+
+    let mut test_object = br#"[{"value": 2}]"#.to_vec();
+    let object = simd_json::to_borrowed_value(&mut test_object).unwrap();
+    builder.add_object(&object);
+    let expected_schema = json!({
+      "type": "array",
+      "items": {
+        "type": "object",
+        "properties": {
+          "op": {"type": "string"},
+          "value": {"type": "integer"},
+          "path": {"type": "string"}
+        }
+      }
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_required_threshold_should_tolerate_rare_missing_properties() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..1000 {
+        let row = match idx {
+            500 => r#"{"id": 500}"#.to_string(),
+            _ if idx % 2 == 0 => format!(r#"{{"id": {idx}, "name": "a", "note": "b"}}"#),
+            _ => format!(r#"{{"id": {idx}, "name": "a"}}"#),
+        };
+        test_objects.extend(row.as_bytes());
+        test_objects.push(b'\n');
+    }
+    let schema_config = SchemaConfig { required_threshold: Some(0.99), ..Default::default() };
+
+    // the required properties should not depend on how the objects were split between chunks
+    let mut builder = SchemaBuilder::with_config(None, schema_config.clone());
+    let schema = build_json_schema(&mut builder, &mut test_objects.clone(), &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: false,
+        ..Default::default()
+    });
+    let mut serial_builder = SchemaBuilder::with_config(None, schema_config.clone());
+    for row in test_objects.split_mut(|byte| *byte == b'\n').filter(|row| !row.is_empty()) {
+        let object = simd_json::to_borrowed_value(row).unwrap();
+        serial_builder.add_object(&object);
+    }
+    assert_eq!(schema, serial_builder.to_schema());
+
+    assert_eq!(schema["required"], json!(["id", "name"]));
+    // the counts are internal to the builder
+    let schema_text = schema.to_string();
+    assert!(!schema_text.contains("x-genson-object-count"));
+    assert!(!schema_text.contains("x-genson-property-counts"));
+
+    // the counts should keep adding up when merging the builder into another builder
+    let mut merged_builder = SchemaBuilder::with_config(None, schema_config);
+    merged_builder.add_builder(&builder);
+    merged_builder.add_schema(json!({
+      "type": "object",
+      "properties": {"id": {"type": "integer"}},
+      "x-genson-object-count": 100,
+      "x-genson-property-counts": {"id": 100}
+    }));
+    let merged_schema = merged_builder.to_schema();
+    assert_eq!(merged_schema["required"], json!(["id"]));
+    assert!(merged_schema.get("x-genson-object-count").is_none());
+}
+
+#[test]
+fn test_stats_annotations_should_count_presence_types_and_nulls() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..40 {
+        let row = match idx % 4 {
+            0 => format!(r#"{{"id": {idx}, "name": null}}"#),
+            1 => format!(r#"{{"id": {idx}.5, "name": "a"}}"#),
+            2 => format!(r#"{{"id": {idx}, "name": "b"}}"#),
+            _ => format!(r#"{{"id": {idx}}}"#),
+        };
+        test_objects.extend(row.as_bytes());
+        test_objects.push(b'\n');
+    }
+    let schema_config = SchemaConfig { stats_annotations: true, ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(None, schema_config.clone());
+    let schema = build_json_schema(&mut builder, &mut test_objects.clone(), &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: false,
+        ..Default::default()
+    });
+
+    let expected_schema = json!({
+      "type": "object",
+      "x-genson-count": 40,
+      "x-genson-null-ratio": 0.0,
+      "x-genson-type-distribution": {"object": 40},
+      "properties": {
+        "id": {
+          "type": "number",
+          "x-genson-count": 40,
+          "x-genson-null-ratio": 0.0,
+          "x-genson-type-distribution": {"integer": 30, "number": 10}
+        },
+        "name": {
+          "type": ["null", "string"],
+          "x-genson-count": 30,
+          "x-genson-null-ratio": 0.3333333333333333,
+          "x-genson-type-distribution": {"null": 10, "string": 20}
+        }
+      },
+      "required": ["id"]
+    });
+    assert_eq!(schema, expected_schema);
+
+    // the stats should not depend on how the objects were split between chunks
+    let mut serial_builder = SchemaBuilder::with_config(None, schema_config);
+    for row in test_objects.split_mut(|byte| *byte == b'\n').filter(|row| !row.is_empty()) {
+        let object = simd_json::to_borrowed_value(row).unwrap();
+        serial_builder.add_object(&object);
+    }
+    assert_eq!(serial_builder.to_schema(), expected_schema);
+}
+
+#[test]
+fn test_number_formats_should_fit_observed_ranges() {
+  let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+    integral_floats: true,
+    number_formats: true,
+    ..Default::default()
+  });
+    for row in [
+        br#"{"small": 1.0, "large": 1, "unsigned": 1, "huge": 1, "ratio": 0.5}"#.to_vec(),
+        br#"{"small": -2147483648, "large": 2147483648, "unsigned": 18446744073709551615, "huge": 100000000000000000000, "ratio": 1e300}"#.to_vec(),
+    ] {
+        let mut row = row;
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+
+    let expected_schema = json!({
+      "type": "object",
+      "properties": {
+        "small": {"type": "integer", "format": "int32"},
+        "large": {"type": "integer", "format": "int64"},
+        "unsigned": {"type": "integer"},
+        "huge": {"type": "integer"},
+        "ratio": {"type": "number", "format": "double"}
+      },
+      "required": ["huge", "large", "ratio", "small", "unsigned"]
+    });
+    assert_eq!(builder.to_schema(), expected_schema);
+
+    let mut merged_builder = SchemaBuilder::with_config(None, SchemaConfig { number_formats: true, ..Default::default() });
+    merged_builder.add_schema(json!({"type": "integer", "format": "int32"}));
+    merged_builder.add_schema(json!({"type": "number", "format": "float"}));
+    assert_eq!(merged_builder.to_schema(), json!({"type": "number", "format": "float"}));
+}
+
+#[test]
+fn test_number_formats_should_only_be_float_when_the_values_fit_exactly() {
+    let config = SchemaConfig { number_formats: true, ..Default::default() };
+    let mut test_objects = br#"{"half": 0.5, "pi": 3.141592653589793, "e": 2.718281828459045, "mixed": 1714561234123, "counter": 16777217}"#.to_vec();
+    for _ in 0..20 {
+        test_objects.extend(br#"
+{"half": 1.25, "pi": 3.0, "e": 2.0, "mixed": 1.5, "counter": 0.5}"#);
+    }
+
+    // the counter is only an integer in one of the chunks
+    let mut builder = SchemaBuilder::with_config(None, config.clone());
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ..Default::default()
+    });
+    assert_eq!(schema, json!({
+      "type": "object",
+      "properties": {
+        "half": {"type": "number", "format": "float"},
+        "pi": {"type": "number", "format": "double"},
+        "e": {"type": "number", "format": "double"},
+        "mixed": {"type": "number", "format": "double"},
+        "counter": {"type": "number", "format": "double"}
+      },
+      "required": ["counter", "e", "half", "mixed", "pi"]
+    }));
+
+    // large integers keep the format they need as floats when merged with other numbers
+    let mut integer_builder = SchemaBuilder::with_config(None, config.clone());
+    let mut row = br#"{"counter": 16777217}"#.to_vec();
+    integer_builder.add_object(&simd_json::to_borrowed_value(&mut row).unwrap());
+    assert_eq!(integer_builder.to_schema(), json!({
+      "type": "object",
+      "properties": {"counter": {"type": "integer", "format": "int32"}},
+      "required": ["counter"]
+    }));
+    let mut merged_builder = SchemaBuilder::with_config(None, config);
+    merged_builder.add_schema(json!({"type": "object", "properties": {"counter": {"type": "number", "format": "float"}}}));
+    merged_builder.add_builder(&integer_builder);
+    assert_eq!(merged_builder.to_schema()["properties"]["counter"], json!({"type": "number", "format": "double"}));
+}
+
+#[test]
+fn test_encoded_scalars_should_be_annotated_or_coerced() {
+    let rows = [
+        br#"{"count": "42", "price": "3.14", "active": "true", "zip": "01234", "id": 1}"#.to_vec(),
+        br#"{"count": "-7", "price": "10", "active": "false", "zip": "98765", "id": "2"}"#.to_vec(),
+    ];
+    let build = |encoded_scalars| {
+        let mut builder = SchemaBuilder::with_config(None, SchemaConfig { encoded_scalars, ..Default::default() });
+        for row in rows.iter() {
+            let mut row = row.clone();
+            let object = simd_json::to_borrowed_value(&mut row).unwrap();
+            builder.add_object(&object);
+        }
+        builder.to_schema()
+    };
+
+    let schema = build(EncodedScalars::Annotate);
+    assert_eq!(schema["properties"]["count"], json!({"type": "string", "x-genson-encoded-type": "integer"}));
+    assert_eq!(schema["properties"]["price"], json!({"type": "string", "x-genson-encoded-type": "number"}));
+    assert_eq!(schema["properties"]["active"], json!({"type": "string", "x-genson-encoded-type": "boolean"}));
+    assert_eq!(schema["properties"]["zip"], json!({"type": "string"}));
+
+    let schema = build(EncodedScalars::Coerce);
+    assert_eq!(schema["properties"]["count"], json!({"type": "integer", "x-genson-encoded-type": "integer"}));
+    assert_eq!(schema["properties"]["active"], json!({"type": "boolean", "x-genson-encoded-type": "boolean"}));
+    // the native integers are merged into the coerced ones
+    assert_eq!(schema["properties"]["id"], json!({"type": "integer", "x-genson-encoded-type": "integer"}));
+
+    // coerced fields should be merged back as strings
+    let mut merged_builder = SchemaBuilder::with_config(None, SchemaConfig {
+      encoded_scalars: EncodedScalars::Coerce,
+      ..Default::default()
+    });
+    merged_builder.add_schema(schema["properties"]["count"].clone());
+    merged_builder.add_schema(json!({"type": "string"}));
+    assert_eq!(merged_builder.to_schema(), json!({"type": "string"}));
+
+    // and the native values should be told apart from them when merging builders
+    let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+      encoded_scalars: EncodedScalars::Coerce,
+      ..Default::default()
+    });
+    for row in rows.iter() {
+        let mut row = row.clone();
+        let object = simd_json::to_borrowed_value(&mut row).unwrap();
+        builder.add_object(&object);
+    }
+    let mut other_builder = SchemaBuilder::with_config(None, SchemaConfig {
+      encoded_scalars: EncodedScalars::Coerce,
+      ..Default::default()
+    });
+    let mut row = br#"{"id": "abc"}"#.to_vec();
+    other_builder.add_object(&simd_json::to_borrowed_value(&mut row).unwrap());
+    builder.add_builder(&other_builder);
+    assert_eq!(builder.to_schema()["properties"]["id"], json!({"type": ["integer", "string"]}));
+}
+
+#[test]
+fn test_epoch_timestamps_should_be_detected_by_unit() {
+    let rows = [
+        br#"{"created_at": 1714561234, "ts": 1714561234123, "updated_time": 1714561234123456, "events": [1714561234], "price": 1714561234}"#.to_vec(),
+        br#"{"created_at": 1700000000, "ts": 1700000000000, "updated_time": 1700000000000000, "events": [1700000000.5], "price": 12}"#.to_vec(),
+    ];
+    let build = |timestamp_name_hints: Vec<String>| {
+        let mut builder = SchemaBuilder::with_config(None, SchemaConfig {
+          epoch_timestamps: true,
+          timestamp_name_hints,
+          ..Default::default()
+        });
+        for row in rows.iter() {
+            let mut row = row.clone();
+            let object = simd_json::to_borrowed_value(&mut row).unwrap();
+            builder.add_object(&object);
+        }
+        builder.to_schema()
+    };
+
+    let schema = build(vec![]);
+    assert_eq!(schema["properties"]["created_at"]["x-genson-timestamp"], "epoch-s");
+    assert_eq!(schema["properties"]["ts"]["x-genson-timestamp"], "epoch-ms");
+    assert_eq!(schema["properties"]["updated_time"]["x-genson-timestamp"], "epoch-us");
+    assert_eq!(schema["properties"]["events"]["items"], json!({"type": "number", "x-genson-timestamp": "epoch-s"}));
+    assert_eq!(schema["properties"]["price"], json!({"type": "integer"}));
+
+    let schema = build(vec!["_at".to_string(), "_TS".to_string()]);
+    assert_eq!(schema["properties"]["created_at"]["x-genson-timestamp"], "epoch-s");
+    assert_eq!(schema["properties"]["ts"], json!({"type": "integer"}));
+    assert_eq!(schema["properties"]["updated_time"], json!({"type": "integer"}));
+}
+
+#[test]
+fn test_decimal_precision_should_keep_digits_as_written() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..20 {
+        let row = format!(r#"{{"amount": {idx}.50, "rates": {{"eur": 0.9{idx}}}, "lines": [{{"qty": 1{idx}}}]}}"#);
+        test_objects.extend(row.as_bytes());
+        test_objects.push(b'\n');
+    }
+    test_objects.extend(br#"{"amount": 123456.7, "rates": {"eur": 1}, "lines": [{"qty": 1e3}]}"#);
+
+    let mut builder = SchemaBuilder::with_config(None, SchemaConfig { decimal_precision: true, ..Default::default() });
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: false,
+        ..Default::default()
+    });
+
+    let properties = &schema["properties"];
+    assert_eq!(properties["amount"]["x-genson-decimal"], json!({"precision": 8, "scale": 2}));
+    assert_eq!(properties["rates"]["properties"]["eur"]["x-genson-decimal"], json!({"precision": 4, "scale": 3}));
+    assert_eq!(properties["lines"]["items"]["properties"]["qty"]["x-genson-decimal"], json!({"precision": 4, "scale": 0}));
+}
+
+#[test]
+fn test_extract_definitions_should_hoist_repeated_subschemas() {
+    let address = json!({"street": "1 Main St", "city": "Springfield", "zip": "12345"});
+    let mut test_objects = json!([
+        {"billing_address": address, "shipping_address": address, "tags": [{"name": "a"}], "labels": [{"name": "b"}]},
+    ]).to_string().into_bytes();
+
+    let config = SchemaConfig { extract_definitions: Some(8), ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(Some("https://json-schema.org/draft/2020-12/schema"), config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: None,
+        ignore_outer_array: false,
+        ..Default::default()
+    });
+
+    let properties = &schema["items"]["properties"];
+    assert_eq!(properties["billing_address"], json!({"$ref": "#/$defs/billing_address"}));
+    assert_eq!(properties["shipping_address"], json!({"$ref": "#/$defs/billing_address"}));
+    assert_eq!(schema["$defs"]["billing_address"]["required"], json!(["city", "street", "zip"]));
+    // the tag and label arrays are smaller than the threshold
+    assert_eq!(properties["tags"]["items"]["type"], "object");
+    assert_eq!(schema["$defs"].as_object().unwrap().len(), 1);
+}
+
+#[test]
+fn test_extract_definitions_should_merge_back_into_the_same_schema() {
+    let config = SchemaConfig { extract_definitions: Some(3), ..Default::default() };
+    let mut builder1 = SchemaBuilder::with_config(None, config.clone());
+    builder1.add_schema(json!({
+        "type": "object",
+        "properties": {
+            "from": {"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+            "to": {"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]},
+        },
+    }));
+    let schema1 = builder1.to_schema();
+    assert_eq!(schema1["properties"]["to"], json!({"$ref": "#/definitions/from"}));
+
+    let mut builder2 = SchemaBuilder::with_config(None, config);
+    builder2.add_schema(schema1.clone());
+    assert_eq!(builder2.to_schema(), schema1);
+}
+
+#[test]
+fn test_recursive_structures_should_refer_to_the_ancestor() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..10 {
+        let row = json!({
+            "title": format!("thread {idx}"),
+            "comments": [{
+                "id": idx,
+                "text": "first",
+                "replies": [{"id": 100 + idx, "text": "second", "replies": [{"id": 200 + idx, "text": "third"}]}],
+            }],
+        });
+        test_objects.extend(row.to_string().as_bytes());
+        test_objects.push(b'\n');
+    }
+
+    let config = SchemaConfig { recursive_structures: true, ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(Some("https://json-schema.org/draft/2020-12/schema"), config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: true,
+        ..Default::default()
+    });
+
+    assert_eq!(schema["properties"]["comments"]["items"], json!({"$ref": "#/$defs/comments"}));
+    assert_eq!(schema["$defs"]["comments"], json!({
+        "type": "object",
+        "properties": {
+            "id": {"type": "integer"},
+            "text": {"type": "string"},
+            "replies": {"type": "array", "items": {"$ref": "#/$defs/comments"}},
+        },
+        "required": ["id", "text"],
+    }));
+}
+
+#[test]
+fn test_recursive_structures_should_refer_to_the_root() {
+    let mut test_objects = json!([
+        {"name": "ceo", "title": "CEO", "reports": [{"name": "cto", "title": "CTO", "reports": [{"name": "dev", "title": "Engineer"}]}]},
+        // an object that only shares one property with its ancestor isn't folded
+        {"name": "intern", "title": "Intern", "mentor": {"name": "dev"}},
+    ]).to_string().into_bytes();
+
+    let config = SchemaConfig { recursive_structures: true, ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: None,
+        ignore_outer_array: false,
+        ..Default::default()
+    });
+
+    assert_eq!(schema["items"]["properties"]["reports"]["items"], json!({"$ref": "#/items"}));
+    assert_eq!(schema["items"]["properties"]["mentor"]["properties"]["name"], json!({"type": "string"}));
+    assert_eq!(schema["items"]["required"], json!(["name", "title"]));
+}
+
+/// Check if the value is valid against the schema, for the keywords of the inferred schemas,
+/// where "$ref" is a JSON pointer into the root schema
+fn is_valid(schema: &Value, root: &Value, value: &Value) -> bool {
+    if let Some(reference) = schema["$ref"].as_str() {
+        if !is_valid(root.pointer(&reference[1..]).unwrap(), root, value) {
+            return false;
+        }
+    }
+    let is_type = |schema_type: &Value| match schema_type.as_str().unwrap() {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        _ => value.is_null(),
+    };
+    let is_type_valid = match &schema["type"] {
+        Value::Null => true,
+        Value::Array(types) => types.iter().any(is_type),
+        schema_type => is_type(schema_type),
+    };
+    let is_object_valid = value.as_object().is_none_or(|object| {
+        let required = schema["required"].as_array().into_iter().flatten();
+        required.map(|property| property.as_str().unwrap()).all(|property| object.contains_key(property))
+            && object.iter().all(|(property, property_value)| match schema["properties"].get(property) {
+                Some(property_schema) => is_valid(property_schema, root, property_value),
+                None => schema["additionalProperties"] != false,
+            })
+    });
+    let is_array_valid = value.as_array().is_none_or(|items| {
+        schema.get("items").is_none_or(|items_schema| items.iter().all(|item| is_valid(items_schema, root, item)))
+    });
+    let is_any_of_valid = schema["anyOf"].as_array().is_none_or(|branches| branches.iter().any(|branch| is_valid(branch, root, value)));
+    let is_one_of_valid = schema["oneOf"].as_array().is_none_or(|branches| {
+        branches.iter().filter(|branch| is_valid(branch, root, value)).count() == 1
+    });
+    is_type_valid && is_object_valid && is_array_valid && is_any_of_valid && is_one_of_valid
+}
+
+#[test]
+fn test_recursive_structures_should_accept_their_input() {
+    let mut deep_chain = json!(1);
+    for _ in 0..100 {
+        deep_chain = json!({"a": deep_chain});
+    }
+    let datasets = [
+        vec![json!({"a": {"a": {"a": 1}}})],
+        vec![deep_chain],
+        vec![
+            json!({"id": 1, "text": "a", "replies": [{"id": 2, "text": "b", "replies": [{"id": 3, "text": "c"}]}]}),
+            json!({"id": 4, "text": "d", "replies": [{"id": 5, "text": "e", "replies": []}]}),
+        ],
+        vec![json!({"name": "ceo", "title": "CEO", "reports": [{"name": "cto", "title": "CTO", "reports": [{"name": "dev", "title": "Engineer", "level": 2}]}]})],
+    ];
+    for records in datasets {
+        let mut test_objects: Vec<u8> = records.iter().flat_map(|record| format!("{}\n", record).into_bytes()).collect();
+        let config = SchemaConfig { recursive_structures: true, ..Default::default() };
+        let mut builder = SchemaBuilder::with_config(None, config);
+        let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+            delimiter: Some(b'\n'),
+            ignore_outer_array: true,
+            ..Default::default()
+        });
+        records.iter().for_each(|record| assert!(is_valid(&schema, &schema, record), "{record} is not valid against {schema}"));
+    }
+}
+
+#[test]
+fn test_recursive_structures_should_not_fold_objects_that_only_share_properties() {
+    let mut test_objects = json!({"owner": {"id": 1, "name": "x", "address": {"id": 2, "name": "y"}}}).to_string().into_bytes();
+    let config = SchemaConfig { recursive_structures: true, ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: None,
+        ignore_outer_array: true,
+        ..Default::default()
+    });
+
+    // an address is nested in the owner, but never in another address
+    assert_eq!(schema["properties"]["owner"]["properties"]["address"], json!({
+        "type": "object",
+        "properties": {"id": {"type": "integer"}, "name": {"type": "string"}},
+        "required": ["id", "name"],
+    }));
+    assert!(schema.get("definitions").is_none());
+}
+
+#[test]
+fn test_recursive_structures_should_not_leak_the_required_threshold_counts() {
+    let mut test_objects = json!({"id": 1, "name": "a", "kids": [
+        {"id": 2, "name": "b", "kids": [{"id": 3, "name": "c"}, {"id": 4, "name": "d"}, {"id": 5, "name": "e"}]},
+    ]}).to_string().into_bytes();
+    let config = SchemaConfig { recursive_structures: true, required_threshold: Some(0.5), ..Default::default() };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: true,
+        ..Default::default()
+    });
+
+    // "kids" is only in 2 of the 5 folded objects
+    assert_eq!(schema, json!({
+        "type": "object",
+        "properties": {
+            "id": {"type": "integer"},
+            "name": {"type": "string"},
+            "kids": {"type": "array", "items": {"$ref": "#"}},
+        },
+        "required": ["id", "name"],
+    }));
+}
+
+#[test]
+fn test_max_depth_should_emit_placeholder_for_deeper_values() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..20 {
+        let row = json!({"id": idx, "event": {"name": "click", "payload": {"user": {"tags": [idx]}}}});
+        test_objects.extend(row.to_string().as_bytes());
+        test_objects.push(b'\n');
+    }
+
+    let config = SchemaConfig {
+        max_depth: Some(2),
+        max_depth_placeholder: Some(json!({"description": "arbitrary user data"})),
+        ..Default::default()
+    };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: true,
+        ..Default::default()
+    });
+
+    // the properties of the objects at the maximum depth aren't tracked
+    assert_eq!(schema["properties"]["event"], json!({
+        "type": "object",
+        "properties": {
+            "name": {"type": "string"},
+            "payload": {"type": "object", "additionalProperties": {"description": "arbitrary user data"}},
+        },
+        "required": ["name", "payload"],
+    }));
+    assert!(!schema.to_string().contains("\"user\""));
+
+    // neither are the properties of the schemas added at the maximum depth
+    let mut builder = SchemaBuilder::with_config(None, SchemaConfig { max_depth: Some(0), ..Default::default() });
+    builder.add_schema(json!({"type": "object", "properties": {"user": {"type": "string"}}, "required": ["user"]}));
+    assert_eq!(builder.to_schema(), json!({"type": "object", "additionalProperties": {}}));
+}
+
+#[test]
+fn test_deeply_nested_objects_should_fail_cleanly() {
+    let nested = |depth: usize| format!(r#"{{"a": {}{}}}"#, "[".repeat(depth), "]".repeat(depth));
+    let config = BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: true,
+        ..Default::default()
+    };
+
+    let mut builder = get_builder(None);
+    let mut test_objects = format!("{}\n{}", nested(2), nested(100_000)).into_bytes();
+    let result = try_build_json_schema(&mut builder, &mut test_objects, &config);
+    assert_eq!(result, Err(BuildError::TooDeeplyNested));
+
+    // the maximum depth includes the outermost object
+    let mut test_objects = nested(MAX_NESTING_DEPTH - 1).into_bytes();
+    let schema = try_build_json_schema(&mut builder, &mut test_objects, &config).unwrap();
+    assert_eq!(schema["properties"]["a"]["type"], "array");
+}
+
+#[test]
+fn test_deeply_nested_values_should_be_cut_off_at_the_maximum_depth() {
+    let mut object = simd_json::BorrowedValue::from(1);
+    for _ in 0..2_000 {
+        object = simd_json::BorrowedValue::Array(vec![object]);
+    }
+    let mut schema = json!({"type": "integer"});
+    for _ in 0..600 {
+        schema = json!({"type": "array", "items": schema});
+    }
+
+    let mut builder = get_builder(None);
+    builder.add_object(&object);
+    builder.add_schema(schema);
+    let mut inferred_depth = 0;
+    let mut inferred_schema = &builder.to_schema();
+    while inferred_schema["type"] == "array" {
+        inferred_depth += 1;
+        inferred_schema = &inferred_schema["items"];
+    }
+    assert_eq!(inferred_depth, MAX_NESTING_DEPTH);
+    assert_eq!(inferred_schema, &json!({}));
+
+    // dropping the object recursively would overflow the stack of the test
+    while let simd_json::BorrowedValue::Array(mut items) = object {
+        object = items.pop().unwrap();
+    }
+}
+
+#[test]
+fn test_path_filters_should_leave_out_excluded_subtrees() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..20 {
+        let row = json!({
+            "id": idx,
+            "user": {"name": "Jane", "ssn": "123-45-6789"},
+            "items": [{"sku": "A1", "internal_cost": 3, "internal_notes": "x"}],
+            "debug": {"trace": [1, 2, 3]},
+        });
+        test_objects.extend(row.to_string().as_bytes());
+        test_objects.push(b'\n');
+    }
+    let build_config = BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: true,
+        ..Default::default()
+    };
+
+    let config = SchemaConfig {
+        exclude_paths: vec!["/debug".to_string(), "/items/*/internal_*".to_string(), "/user/ssn".to_string()],
+        ..Default::default()
+    };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects.clone(), &build_config);
+    assert_eq!(schema["required"], json!(["id", "items", "user"]));
+    assert_eq!(schema["properties"]["items"]["items"]["properties"], json!({"sku": {"type": "string"}}));
+    assert_eq!(schema["properties"]["user"]["required"], json!(["name"]));
+
+    let config = SchemaConfig {
+        include_paths: vec!["/user/**".to_string(), "!/user/ssn".to_string()],
+        ..Default::default()
+    };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &build_config);
+    assert_eq!(schema, json!({
+        "type": "object",
+        "properties": {
+            "user": {"type": "object", "properties": {"name": {"type": "string"}}, "required": ["name"]},
+        },
+        "required": ["user"],
+    }));
+}
+
+#[test]
+fn test_excluded_paths_should_match_map_and_pattern_values_by_wildcard() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..5 {
+        let row = json!({
+            "users": {
+                format!("user-{idx}"): {"name": "Jane", "password": "hunter2"},
+                format!("admin-{idx}"): {"name": "Joe", "password": "hunter3"},
+            },
+            "headers": {"x-request-id": {"value": "abc", "secret": "s3cr3t"}},
+        });
+        test_objects.extend(row.to_string().as_bytes());
+        test_objects.push(b'\n');
+    }
+    let build_config = BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: true,
+        ..Default::default()
+    };
+
+    let config = SchemaConfig {
+        map_key_threshold: Some(3),
+        pattern_properties: vec![Regex::new("^x-").unwrap()],
+        exclude_paths: vec!["/users/*/password".to_string(), "/headers/*/secret".to_string()],
+        ..Default::default()
+    };
+    let mut builder = SchemaBuilder::with_config(None, config);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &build_config);
+    assert_eq!(schema["properties"]["users"], json!({
+        "type": "object",
+        "additionalProperties": {
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"],
+        },
+    }));
+    assert_eq!(schema["properties"]["headers"]["patternProperties"]["^x-"], json!({
+        "type": "object",
+        "properties": {"value": {"type": "string"}},
+        "required": ["value"],
+    }));
+    assert!(!schema.to_string().contains("\"password\""));
+    assert!(!schema.to_string().contains("\"secret\""));
+}
+
+#[test]
+fn test_selector_should_build_schema_from_selected_records() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..10 {
+        let page = json!({"data": {"items": [{"id": idx, "name": "a"}, {"id": idx + 100}]}, "next": idx + 1});
+        test_objects.extend(page.to_string().as_bytes());
+        test_objects.push(b'\n');
+    }
+
+    let mut builder = get_builder(None);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ignore_outer_array: false,
+        selector: Some(Selector::parse(".data.items[]").unwrap()),
+        ..Default::default()
+    });
+    assert_eq!(schema, json!({
+        "type": "object",
+        "properties": {"id": {"type": "integer"}, "name": {"type": "string"}},
+        "required": ["id"],
+    }));
+
+    // an array is a single document to select the records from
+    let mut test_array = json!([{"data": {"items": [{"id": 1}]}}, {"data": {"items": [{"id": "2"}]}}]).to_string().into_bytes();
+    let mut builder = get_builder(None);
+    let schema = build_json_schema(&mut builder, &mut test_array, &BuildConfig {
+        delimiter: None,
+        ignore_outer_array: false,
+        selector: Some(Selector::parse(".[].data.items[0].id").unwrap()),
+        ..Default::default()
+    });
+    assert_eq!(schema, json!({"type": ["integer", "string"]}));
+}
+
+#[test]
+fn test_sampling_should_pick_records_before_parsing() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..100 {
+        test_objects.extend(format!("{{\"idx\": {idx}}}\n").as_bytes());
+    }
+    let config = SchemaConfig { max_examples: 10, stats_annotations: true, ..Default::default() };
+
+    for delimiter in [Some(b'\n'), None] {
+        let mut builder = SchemaBuilder::with_config(None, config.clone());
+        let schema = build_json_schema(&mut builder, &mut test_objects.clone(), &BuildConfig {
+            delimiter,
+            skip: 10,
+            sample_every: Some(3),
+            max_records: Some(5),
+            ..Default::default()
+        });
+        assert_eq!(schema["x-genson-count"], 5);
+        let mut examples: Vec<u64> = schema["properties"]["idx"]["examples"].as_array().unwrap()
+            .iter().map(|example| example.as_u64().unwrap()).collect();
+        examples.sort();
+        assert_eq!(examples, vec![10, 13, 16, 19, 22]);
+    }
+
+    // the records selected from a single JSON array are sampled too
+    let rows: Vec<String> = (0..100).map(|idx| format!("{{\"idx\": {idx}}}")).collect();
+    let mut array_objects = format!("[{}]", rows.join(",")).into_bytes();
+    let mut builder = SchemaBuilder::with_config(None, config.clone());
+    let schema = build_json_schema(&mut builder, &mut array_objects, &BuildConfig {
+        selector: Some(Selector::parse(".[]").unwrap()),
+        skip: 10,
+        sample_every: Some(3),
+        max_records: Some(5),
+        ..Default::default()
+    });
+    let mut examples: Vec<u64> = schema["properties"]["idx"]["examples"].as_array().unwrap()
+        .iter().map(|example| example.as_u64().unwrap()).collect();
+    examples.sort();
+    assert_eq!(examples, vec![10, 13, 16, 19, 22]);
+
+    // as are the records selected from single objects, counted across the documents
+    let mut single_object = json!({"data": {"items": [{"id": 1}, {"id": "2"}]}}).to_string().into_bytes();
+    let mut builder = get_builder(None);
+    let schema = build_json_schema(&mut builder, &mut single_object, &BuildConfig {
+        selector: Some(Selector::parse(".data.items[]").unwrap()),
+        max_records: Some(1),
+        ..Default::default()
+    });
+    assert_eq!(schema, json!({"type": "object", "properties": {"id": {"type": "integer"}}, "required": ["id"]}));
+    let pages: Vec<String> = (0..10).map(|page| format!("{{\"items\": [{{\"idx\": {}}}, {{\"idx\": {}}}]}}\n", 2 * page, 2 * page + 1)).collect();
+    for delimiter in [Some(b'\n'), None] {
+        let mut builder = SchemaBuilder::with_config(None, config.clone());
+        let schema = build_json_schema(&mut builder, &mut pages.concat().into_bytes(), &BuildConfig {
+            delimiter,
+            selector: Some(Selector::parse(".items[]").unwrap()),
+            skip: 3,
+            sample_every: Some(4),
+            max_records: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(schema["x-genson-count"], 3);
+        let mut examples: Vec<u64> = schema["properties"]["idx"]["examples"].as_array().unwrap()
+            .iter().map(|example| example.as_u64().unwrap()).collect();
+        examples.sort();
+        assert_eq!(examples, vec![3, 7, 11]);
+    }
+
+    let sample = |seed: u64| {
+        let mut builder = SchemaBuilder::with_config(None, config.clone());
+        build_json_schema(&mut builder, &mut test_objects.clone(), &BuildConfig {
+            delimiter: Some(b'\n'),
+            sample_rate: Some(0.3),
+            seed,
+            ..Default::default()
+        })
+    };
+    let schema = sample(42);
+    assert_eq!(schema, sample(42));
+    let count = schema["x-genson-count"].as_u64().unwrap();
+    assert!((15..=45).contains(&count), "unexpected sample size {}", count);
+}
+
+#[test]
+fn test_stable_records_should_stop_ingestion_once_the_schema_is_stable() {
+    let mut test_objects: Vec<u8> = vec![];
+    for idx in 0..1000 {
+        test_objects.extend(format!("{{\"idx\": {idx}, \"name\": \"a\"}}\n").as_bytes());
+    }
+    // the variation comes after the schema is stable, so it's never read
+    test_objects.extend(b"{\"idx\": 1.5, \"extra\": true}\n");
+
+    for delimiter in [Some(b'\n'), None] {
+        let mut builder = SchemaBuilder::new(None);
+        let schema = build_json_schema(&mut builder, &mut test_objects.clone(), &BuildConfig {
+            delimiter,
+            stable_records: Some(100),
+            ..Default::default()
+        });
+        assert_eq!(builder.object_count(), 200);
+        assert_eq!(schema, json!({
+            "type": "object",
+            "properties": {"idx": {"type": "integer"}, "name": {"type": "string"}},
+            "required": ["idx", "name"]
+        }));
+    }
+
+    // the variation is within the first chunks, so ingestion goes on
+    let mut test_objects = b"{\"idx\": 1}\n{\"idx\": 1.5, \"extra\": true}\n".to_vec();
+    for idx in 0..10 {
+        test_objects.extend(format!("{{\"idx\": {idx}}}\n").as_bytes());
+    }
+    let mut builder = SchemaBuilder::new(None);
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        stable_records: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(builder.object_count(), 4);
+    assert_eq!(schema["properties"]["idx"]["type"], "number");
+    assert!(schema["properties"]["extra"].is_object());
+}
+
+#[test]
+fn test_structure_changes_should_only_count_changes_to_the_structure() {
+    let mut builder = SchemaBuilder::new(None);
+    let mut add_object = |json: &str| {
+        let mut json = json.as_bytes().to_vec();
+        builder.add_object(&simd_json::to_borrowed_value(&mut json).unwrap());
+        builder.structure_changes()
+    };
+    let changes = add_object(r#"{"a": 1, "b": "x"}"#);
+    assert!(changes > 0);
+    assert_eq!(add_object(r#"{"a": 2, "b": "y"}"#), changes);
+    assert!(add_object(r#"{"a": 2}"#) > changes);
+}
+
+#[test]
+fn test_seed_schema_should_keep_its_keywords_while_data_extends_it() {
+    let mut builder = SchemaBuilder::new(Some("AUTO"));
+    builder.add_schema(json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "description": "A user",
+        "properties": {
+            "id": {"type": "integer", "minimum": 0},
+            "tags": {"type": "array", "description": "The tags", "maxItems": 5, "items": {"type": "string", "format": "slug"}}
+        },
+        "required": ["id"]
+    }));
+    let mut test_objects = b"{\"id\": 1, \"tags\": [\"a\"], \"name\": \"x\"}\n{\"id\": 2, \"name\": \"y\"}".to_vec();
+    let schema = build_json_schema(&mut builder, &mut test_objects, &BuildConfig {
+        delimiter: Some(b'\n'),
+        ..Default::default()
+    });
+    assert_eq!(schema, json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "description": "A user",
+        "properties": {
+            "id": {"type": "integer", "minimum": 0},
+            "name": {"type": "string"},
+            "tags": {"type": "array", "description": "The tags", "maxItems": 5, "items": {"type": "string", "format": "slug"}}
+        },
+        "required": ["id"]
+    }));
+}
+
+#[test]
+fn test_add_schema_should_keep_the_first_schema_uri() {
+    let mut builder = SchemaBuilder::new(Some("AUTO"));
+    builder.add_schema(json!({"$schema": "http://json-schema.org/draft-07/schema#", "type": "string"}));
+    builder.add_schema(json!({"$schema": "http://json-schema.org/draft-04/schema#", "type": "string"}));
+    assert_eq!(builder.to_schema(), json!({"$schema": "http://json-schema.org/draft-07/schema#", "type": "string"}));
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde_json::{json, Value};
+
+/// Write the content to a file in a temporary directory of the test
+fn write_test_file(test_name: &str, file_name: &str, content: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("genson-rs-{}-{}", test_name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(file_name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn run_cli(args: &[&str]) -> Value {
+    run_cli_with_threads(args, None)
+}
+
+fn run_cli_with_threads(args: &[&str], threads: Option<usize>) -> Value {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_genson-rs"));
+    if let Some(threads) = threads {
+        command.env("RAYON_NUM_THREADS", threads.to_string());
+    }
+    let output = command.args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_seed_schema_should_be_extended_by_the_data() {
+    let seed = write_test_file("seed", "seed.json", &json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "properties": {"id": {"type": "integer", "description": "The id"}},
+        "patternProperties": {"^x-": {}},
+        "required": ["id"]
+    }).to_string());
+    let data = write_test_file("seed", "data.json", "{\"id\": 1, \"x-a\": \"v\"}\n{\"id\": 2, \"name\": \"n\"}\n");
+    let other_data = write_test_file("seed", "other_data.json", "{\"id\": 3, \"x-b\": \"w\", \"name\": \"m\"}\n");
+
+    let schema = run_cli(&[
+        "--schema", seed.to_str().unwrap(),
+        "--delimiter", "newline",
+        data.to_str().unwrap(),
+        other_data.to_str().unwrap(),
+    ]);
+    // the properties matching the seed's pattern go to the pattern, not to "properties"
+    assert_eq!(schema, json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "type": "object",
+        "properties": {
+            "id": {"type": "integer", "description": "The id"},
+            "name": {"type": "string"}
+        },
+        "patternProperties": {"^x-": {"type": "string"}},
+        "required": ["id"]
+    }));
+}
+
+#[test]
+fn test_seed_schema_counts_should_only_be_added_once() {
+    let seed = write_test_file("seed_counts", "seed.json", &json!({
+        "type": "object",
+        "properties": {"id": {"type": "integer", "x-genson-count": 10}},
+        "required": ["id"],
+        "x-genson-count": 10
+    }).to_string());
+    let records: String = (0..20).map(|id| format!("{{\"id\": {}}}\n", id)).collect();
+    let data = write_test_file("seed_counts", "data.json", &records);
+    let other_data = write_test_file("seed_counts", "other_data.json", &records);
+
+    for threads in [1, 4] {
+        let schema = run_cli_with_threads(&[
+            "--schema", seed.to_str().unwrap(),
+            "--delimiter", "newline",
+            "--stats-annotations",
+            data.to_str().unwrap(),
+            other_data.to_str().unwrap(),
+        ], Some(threads));
+        assert_eq!(schema["x-genson-count"], 50, "with {} threads", threads);
+        assert_eq!(schema["properties"]["id"]["x-genson-count"], 50, "with {} threads", threads);
+    }
+}